@@ -3,16 +3,45 @@
 mod project;
 
 use project::{
-    backup_local_database, create_story, delete_story, ensure_project, export_project,
-    export_project_to_local, export_story, export_story_to_local, get_bootstrap_state,
-    import_project, import_story, initialize_project_root, open_project_root, open_story_database,
-    open_story_folder, pick_project_root, rename_story, update_global_library, update_settings,
-    update_story_library, update_tree, ProjectState,
+    apply_template, archive_story, backup_local_database, backup_local_database_incremental,
+    batch_update_workspaces, close_project, compute_backlinks, create_story, current_project_root,
+    dedupe_library, delete_export, delete_story, diff_exports, duplicate_template, ensure_project,
+    export_project, export_project_encrypted, export_project_to_local, export_shared_library,
+    export_stories, export_stories_bundle_to_local, export_stories_to_local, export_story,
+    export_story_to_folder, export_story_to_local, find_replace, flush_pending_writes,
+    flush_pending_writes_on_exit, get_auto_backup_status, get_bootstrap_state, get_config,
+    get_cover_palette, get_recent_activity, get_shared_library, get_story_library, get_workspace,
+    handle_launch_args, import_asset, import_project, import_project_encrypted,
+    import_shared_library, import_story, import_story_from_folder, init_git_repo,
+    initialize_project_root, list_assets, list_exports, list_snapshots, list_stories,
+    merge_project_file, migrate_legacy_folders, move_project_root, move_tree_node, open_asset,
+    open_exports_folder, open_project_root, open_project_root_folder, open_project_root_readonly,
+    open_story_database, open_story_folder, overwrite_external_manifest_changes, pick_project_root,
+    project_size, recategorize_settings, regenerate_thumbnails, remove_unused_tags,
+    rename_category, rename_project_root, rename_story, reorder_categories, reorder_stories,
+    repair_duplicate_folders, restore_snapshot, schedule_settings_write, search_project_ranked,
+    search_stories, snapshot_story, tag_usage, take_pending_import, toggle_story_pin, tree_outline,
+    unarchive_story, undo_last, update_config, update_global_library, update_settings,
+    update_story_cover, update_story_library, update_tree, update_tree_node, validate_export_file,
+    ProjectState,
 };
 
 fn main() {
     tauri::Builder::default()
+        .plugin(tauri_plugin_single_instance::init(|app, argv, _cwd| {
+            handle_launch_args(app, argv.get(1..).unwrap_or_default());
+        }))
+        .plugin(
+            tauri_plugin_log::Builder::new()
+                .level(log::LevelFilter::Info)
+                .build(),
+        )
         .manage(ProjectState::default())
+        .setup(|app| {
+            let args = std::env::args().skip(1).collect::<Vec<_>>();
+            handle_launch_args(app.handle(), &args);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             ensure_project,
             get_bootstrap_state,
@@ -25,6 +54,7 @@ fn main() {
             update_settings,
             update_story_library,
             update_global_library,
+            dedupe_library,
             update_tree,
             export_project,
             export_story,
@@ -35,7 +65,87 @@ fn main() {
             import_story,
             open_story_folder,
             open_story_database,
+            compute_backlinks,
+            find_replace,
+            get_config,
+            update_config,
+            get_workspace,
+            export_project_encrypted,
+            import_project_encrypted,
+            diff_exports,
+            merge_project_file,
+            overwrite_external_manifest_changes,
+            close_project,
+            export_stories,
+            export_stories_to_local,
+            export_shared_library,
+            import_shared_library,
+            reorder_stories,
+            toggle_story_pin,
+            archive_story,
+            unarchive_story,
+            duplicate_template,
+            recategorize_settings,
+            move_tree_node,
+            get_recent_activity,
+            project_size,
+            open_exports_folder,
+            open_project_root_folder,
+            schedule_settings_write,
+            flush_pending_writes,
+            snapshot_story,
+            list_snapshots,
+            restore_snapshot,
+            undo_last,
+            batch_update_workspaces,
+            init_git_repo,
+            rename_project_root,
+            move_project_root,
+            list_stories,
+            take_pending_import,
+            validate_export_file,
+            backup_local_database_incremental,
+            get_auto_backup_status,
+            search_stories,
+            search_project_ranked,
+            tag_usage,
+            remove_unused_tags,
+            reorder_categories,
+            rename_category,
+            get_story_library,
+            get_shared_library,
+            apply_template,
+            migrate_legacy_folders,
+            repair_duplicate_folders,
+            current_project_root,
+            open_project_root_readonly,
+            get_cover_palette,
+            update_story_cover,
+            import_asset,
+            regenerate_thumbnails,
+            list_assets,
+            open_asset,
+            export_story_to_folder,
+            import_story_from_folder,
+            export_stories_bundle_to_local,
+            list_exports,
+            delete_export,
+            update_tree_node,
+            tree_outline,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| match event {
+            tauri::RunEvent::Exit => flush_pending_writes_on_exit(app),
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            tauri::RunEvent::Opened { urls } => {
+                let paths = urls
+                    .iter()
+                    .filter_map(|url| url.to_file_path().ok())
+                    .map(|path| path.to_string_lossy().to_string())
+                    .collect::<Vec<_>>();
+                handle_launch_args(app, &paths);
+            }
+            _ => {}
+        });
 }
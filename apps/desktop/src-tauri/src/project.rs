@@ -1,21 +1,163 @@
 use std::{
+    collections::{HashMap, HashSet},
     fs,
+    io::Write,
     path::{Path, PathBuf},
     process::Command,
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use chrono::Utc;
-use rusqlite::{params, Connection, OptionalExtension};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Manager, State};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager, State};
 use uuid::Uuid;
 
 const CURRENT_SCHEMA_VERSION: i64 = 1;
+// Everything else under the project root (project.json, config.json, activity.log,
+// the stories folder) is project content; these entries, plus the configured exports
+// folder, are runtime state or generated output.
+const BACKUP_EXCLUDED_PATHS: &[&str] = &[".lock", ".session.json", ".trash"];
+const ACTIVITY_LOG_MAX_BYTES: u64 = 1024 * 1024;
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+const SELF_WRITE_SUPPRESSION: Duration = Duration::from_secs(2);
+const READ_ONLY_PROJECT: &str = "ReadOnly: 项目以只读模式打开，无法修改";
+const MANIFEST_CHANGED_EXTERNALLY: &str =
+    "ManifestChangedExternally: 项目元信息已被外部修改，请重新加载项目";
+const ENCRYPTED_EXPORT_MAGIC: &[u8; 8] = b"TKCPENC1";
+const ENCRYPTED_EXPORT_SALT_LEN: usize = 16;
+const ENCRYPTED_EXPORT_NONCE_LEN: usize = 12;
+const UNDO_STACK_LIMIT: usize = 20;
+const STORY_TITLE_MAX_CHARS: usize = 100;
+const LOCK_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const LOCK_STALE_AFTER_SECS: i64 = 120;
+const PALETTE_COLORS: [&str; 5] = [
+    "var(--coral-400)",
+    "var(--violet-400)",
+    "var(--teal-400)",
+    "var(--amber-400)",
+    "var(--rose-400)",
+];
+const ASSET_REFERENCE_KEYS: [&str; 2] = ["imageUrl", "videoUrl"];
+const SEARCH_INDEX_SCHEMA: &str = "CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(node_id UNINDEXED, kind UNINDEXED, title, body, tokenize = 'trigram')";
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+const VIDEO_EXTENSIONS: [&str; 4] = ["mp4", "webm", "mov", "m4v"];
+const THUMBNAIL_MAX_SIZE: u32 = 256;
+const BUILTIN_TEMPLATE_TYPES: [&str; 4] = ["character", "location", "item", "event"];
 
 #[derive(Default)]
 pub struct ProjectState {
     project_root: Mutex<Option<PathBuf>>,
+    manifest_mtime: Mutex<Option<SystemTime>>,
+    watcher: Mutex<Option<ProjectWatcher>>,
+    last_self_write: Arc<Mutex<Option<Instant>>>,
+    story_locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    manifest_lock: Mutex<()>,
+    pending_settings: Mutex<HashMap<String, PendingSettingsWrite>>,
+    settings_wakeup: Condvar,
+    settings_worker: AtomicBool,
+    undo_stack: Mutex<Vec<UndoEntry>>,
+    manifest_cache: Mutex<Option<ManifestCache>>,
+    config_cache: Mutex<Option<ConfigCache>>,
+    pending_import: Mutex<Option<ExportPreview>>,
+    auto_backup: Mutex<Option<AutoBackupTimer>>,
+    lock_heartbeat: Mutex<Option<LockHeartbeat>>,
+    read_only: AtomicBool,
+}
+
+struct LockHeartbeat {
+    root: PathBuf,
+    stop: Arc<AtomicBool>,
+}
+
+struct AutoBackupTimer {
+    root: PathBuf,
+    interval_minutes: u64,
+    stop: Arc<AtomicBool>,
+    status: Arc<Mutex<AutoBackupStatus>>,
+}
+
+struct ManifestCache {
+    root: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    manifest: ProjectManifest,
+}
+
+struct ConfigCache {
+    root: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    config: ProjectConfig,
+}
+
+enum UndoEntry {
+    DeleteStory {
+        index: usize,
+        entry: StoryManifestEntry,
+        trash_path: PathBuf,
+    },
+    RenameStory {
+        story_id: String,
+        title: String,
+        folder_name: String,
+    },
+    StoryLibrary {
+        story_id: String,
+        library: SettingLibrary,
+    },
+    GlobalLibrary {
+        library: SettingLibrary,
+    },
+}
+
+struct PendingSettingsWrite {
+    root: PathBuf,
+    settings: Vec<serde_json::Value>,
+    due_at: Instant,
+}
+
+struct ProjectWatcher {
+    root: PathBuf,
+    _watcher: RecommendedWatcher,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectChangedEvent {
+    project_path: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StoryChangedEvent {
+    story_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SettingsWriteFailedEvent {
+    story_id: String,
+    error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectMoveProgressEvent {
+    copied_bytes: u64,
+    total_bytes: u64,
+    current: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,9 +165,16 @@ pub struct ProjectState {
 pub struct Story {
     pub id: String,
     pub title: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(default)]
     pub updated_at: String,
+    #[serde(default)]
     pub cover_color: String,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub archived: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,7 +190,88 @@ pub struct SettingCustomField {
     pub name: String,
     pub value: String,
     #[serde(default)]
-    pub size: Option<String>,
+    pub size: CustomFieldSize,
+    #[serde(default)]
+    pub kind: CustomFieldKind,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CustomFieldKind {
+    #[default]
+    Text,
+    Number,
+    Date,
+    Other(String),
+}
+
+impl CustomFieldKind {
+    fn as_str(&self) -> &str {
+        match self {
+            CustomFieldKind::Text => "text",
+            CustomFieldKind::Number => "number",
+            CustomFieldKind::Date => "date",
+            CustomFieldKind::Other(value) => value,
+        }
+    }
+}
+
+impl Serialize for CustomFieldKind {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CustomFieldKind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Option::<String>::deserialize(deserializer)?;
+        Ok(match raw.as_deref().map(str::trim) {
+            None | Some("") | Some("text") => CustomFieldKind::Text,
+            Some("number") => CustomFieldKind::Number,
+            Some("date") => CustomFieldKind::Date,
+            Some(other) => CustomFieldKind::Other(other.to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CustomFieldSize {
+    Small,
+    #[default]
+    Medium,
+    Large,
+    Full,
+    Custom(String),
+}
+
+impl CustomFieldSize {
+    fn as_str(&self) -> &str {
+        match self {
+            CustomFieldSize::Small => "small",
+            CustomFieldSize::Medium => "medium",
+            CustomFieldSize::Large => "large",
+            CustomFieldSize::Full => "full",
+            CustomFieldSize::Custom(value) => value,
+        }
+    }
+}
+
+impl Serialize for CustomFieldSize {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CustomFieldSize {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Option::<String>::deserialize(deserializer)?;
+        Ok(match raw.as_deref().map(str::trim) {
+            None | Some("") | Some("medium") => CustomFieldSize::Medium,
+            Some("small") => CustomFieldSize::Small,
+            Some("large") => CustomFieldSize::Large,
+            Some("full") => CustomFieldSize::Full,
+            Some(other) => CustomFieldSize::Custom(other.to_string()),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,6 +328,10 @@ pub struct Workspace {
     pub tree: Vec<serde_json::Value>,
     #[serde(default = "default_library")]
     pub library: SettingLibrary,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub repaired: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recovered: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,6 +348,18 @@ pub struct ProjectData {
 pub struct EnsureProjectResponse {
     pub project_path: String,
     pub data: ProjectData,
+    pub last_story_id: Option<String>,
+    pub recovered_story_ids: Vec<String>,
+    pub manifest_warning: Option<ManifestWarning>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestWarning {
+    pub unknown_fields: Vec<String>,
+    pub defaulted_fields: Vec<String>,
+    #[serde(default)]
+    pub duplicate_folder_names: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,6 +368,10 @@ pub struct BootstrapState {
     pub needs_setup: bool,
     pub default_root_path: String,
     pub active_root_path: Option<String>,
+    pub schema_version: Option<i64>,
+    pub app_version: String,
+    pub needs_upgrade: bool,
+    pub read_only: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -129,6 +379,15 @@ pub struct BootstrapState {
 pub struct CreateStoryInput {
     pub title: String,
     pub description: String,
+    #[serde(default)]
+    pub cover_color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateStoryResult {
+    pub story: Story,
+    pub workspace: Workspace,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -140,6 +399,196 @@ pub struct ExportedProjectData {
     pub data: ProjectData,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceUpdate {
+    pub story_id: String,
+    #[serde(default)]
+    pub settings: Option<Vec<serde_json::Value>>,
+    #[serde(default)]
+    pub tree: Option<Vec<serde_json::Value>>,
+    #[serde(default)]
+    pub library: Option<SettingLibrary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceUpdateResult {
+    pub story_id: String,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportPreview {
+    pub path: String,
+    pub kind: String,
+    pub schema_version: i64,
+    pub exported_at: String,
+    pub story_titles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub story_id: String,
+    pub story_title: String,
+    pub node_id: String,
+    pub kind: String,
+    pub title: String,
+    pub snippet: String,
+    pub rank: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoBackupStatus {
+    pub enabled: bool,
+    pub interval_minutes: u64,
+    pub last_backup_at: Option<String>,
+    pub next_backup_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigratedFolder {
+    pub story_id: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderRepair {
+    pub story_id: String,
+    pub old_folder_name: String,
+    pub new_folder_name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    pub moved: Vec<MigratedFolder>,
+    pub unmatched: Vec<String>,
+    pub conflicts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportInfo {
+    pub name: String,
+    pub kind: String,
+    pub size_bytes: u64,
+    pub modified_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlineEntry {
+    pub id: String,
+    pub title: String,
+    pub node_type: Option<String>,
+    pub depth: usize,
+    pub word_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupReport {
+    pub backup_path: String,
+    pub copied: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupState {
+    backup_dir: String,
+    files: HashMap<String, BackupFileState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupFileState {
+    size: u64,
+    modified_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportValidation {
+    pub valid: bool,
+    pub kind: Option<String>,
+    pub schema_version: Option<i64>,
+    pub story_titles: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedTemplate {
+    pub node: serde_json::Value,
+    pub used_keys: Vec<String>,
+    pub appended: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagUsage {
+    pub counts: HashMap<String, usize>,
+    pub unused: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+    pub imported_story_ids: Vec<String>,
+    pub overwritten_story_ids: Vec<String>,
+    pub warning: Option<ImportWarning>,
+    #[serde(default)]
+    pub deduplicated_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetFile {
+    pub reference: String,
+    pub kind: String,
+    pub size_bytes: u64,
+    pub referenced: bool,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetInventory {
+    pub files: Vec<AssetFile>,
+    pub broken_references: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedAsset {
+    pub reference: String,
+    pub size_bytes: u64,
+    pub deduplicated_bytes: u64,
+    pub thumbnail_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportWarning {
+    pub newer_schema_version: Option<i64>,
+    pub dropped_fields: Vec<String>,
+    pub stories_without_workspace: Vec<String>,
+    pub orphaned_workspaces: Vec<String>,
+    #[serde(default)]
+    pub invalid_timestamps: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExportedStoryData {
@@ -148,13 +597,245 @@ pub struct ExportedStoryData {
     pub exported_at: String,
     pub story: Story,
     pub workspace: Workspace,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace_updated_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder_name: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub assets: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_assets: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryFolderMeta {
+    pub app: String,
+    pub schema_version: i64,
+    pub exported_at: String,
+    pub story: Story,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace_updated_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindReplaceOptions {
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub whole_word: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaceMatch {
+    pub node_id: String,
+    pub field: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaceResult {
+    pub replacements: usize,
+    pub setting_node_ids: Vec<String>,
+    pub tree_node_ids: Vec<String>,
+    pub matches: Vec<ReplaceMatch>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProjectConfig {
+    pub autosave_interval_ms: u64,
+    pub default_export_format: String,
+    pub backup_retention: usize,
+    pub theme: String,
+    pub locale: String,
+    pub stories_dir: Option<String>,
+    pub exports_dir: Option<String>,
+    pub settings_write_debounce_ms: u64,
+    pub snapshot_interval_minutes: u64,
+    pub snapshot_retention: usize,
+    pub auto_commit: bool,
+    pub auto_backup_interval_minutes: u64,
+    pub backup_dir: Option<String>,
+    pub custom_template_types: Vec<String>,
+    pub custom_cover_colors: Vec<String>,
+    pub max_asset_bytes: u64,
+    pub allowed_image_extensions: Vec<String>,
+    pub allowed_video_extensions: Vec<String>,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        ProjectConfig {
+            autosave_interval_ms: 1500,
+            default_export_format: "json".to_string(),
+            backup_retention: 10,
+            theme: "system".to_string(),
+            locale: "zh-CN".to_string(),
+            stories_dir: None,
+            exports_dir: None,
+            settings_write_debounce_ms: 500,
+            snapshot_interval_minutes: 30,
+            snapshot_retention: 20,
+            auto_commit: false,
+            auto_backup_interval_minutes: 0,
+            backup_dir: None,
+            custom_template_types: vec![],
+            custom_cover_colors: vec![],
+            max_asset_bytes: 200 * 1024 * 1024,
+            allowed_image_extensions: IMAGE_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            allowed_video_extensions: VIDEO_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedLibraryData {
+    pub app: String,
+    pub schema_version: i64,
+    pub exported_at: String,
+    pub library: SettingLibrary,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LibraryImportMode {
+    Replace,
+    #[default]
+    Merge,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryDiff {
+    pub story_id: String,
+    pub title: String,
+    pub updated_at_before: String,
+    pub updated_at_after: String,
+    pub metadata_changed: bool,
+    pub settings_changed: bool,
+    pub tree_changed: bool,
+    pub library_changed: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectDiff {
+    pub added: Vec<Story>,
+    pub removed: Vec<Story>,
+    pub modified: Vec<StoryDiff>,
+    pub unchanged_count: usize,
+    pub shared_library_changed: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeStrategy {
+    #[default]
+    NewestWins,
+    KeepBoth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeAction {
+    Added,
+    Unchanged,
+    KeptLocal,
+    Replaced,
+    KeptBoth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeDecision {
+    pub story_id: String,
+    pub title: String,
+    pub action: MergeAction,
+    pub merged_story_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeReport {
+    pub decisions: Vec<MergeDecision>,
+    pub shared_library_changed: bool,
+    #[serde(default)]
+    pub invalid_timestamps: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoResult {
+    pub action: String,
+    pub story_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorySnapshot {
+    pub timestamp: String,
+    pub label: Option<String>,
+    pub created_at: String,
+    #[serde(default)]
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityEntry {
+    pub timestamp: String,
+    pub story_id: String,
+    pub action: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorySize {
+    pub story_id: String,
+    pub title: String,
+    pub db_bytes: u64,
+    pub assets_bytes: u64,
+    pub total_bytes: u64,
+    pub total_human: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectSize {
+    pub total_bytes: u64,
+    pub total_human: String,
+    pub stories: Vec<StorySize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ProjectSession {
+    last_story_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ProjectManifest {
     app: String,
+    #[serde(default)]
     schema_version: i64,
+    #[serde(default)]
     created_at: String,
     #[serde(default = "default_library")]
     shared_library: SettingLibrary,
@@ -166,6 +847,8 @@ struct ProjectManifest {
 struct StoryManifestEntry {
     story: Story,
     folder_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    order: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -178,7 +861,7 @@ struct LegacyProjectManifest {
 }
 
 fn now_rfc3339() -> String {
-    Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
 }
 
 fn default_root_path(app: &AppHandle) -> Result<PathBuf, String> {
@@ -229,796 +912,8208 @@ fn project_manifest_path(root: &Path) -> PathBuf {
     root.join("project.json")
 }
 
-fn stories_root(root: &Path) -> PathBuf {
-    root.join("stories")
+fn project_config_path(root: &Path) -> PathBuf {
+    root.join("config.json")
 }
 
-fn slugify_story_title(title: &str) -> String {
-    let mut slug = String::new();
-    let mut last_dash = false;
+fn read_config(root: &Path) -> Result<ProjectConfig, String> {
+    let path = project_config_path(root);
+    if !path.exists() {
+        return Ok(ProjectConfig::default());
+    }
+    let raw = fs::read_to_string(path).map_err(|error| format!("读取项目配置失败: {error}"))?;
+    serde_json::from_str::<ProjectConfig>(&raw)
+        .map_err(|error| format!("解析项目配置失败: {error}"))
+}
 
-    for ch in title.chars() {
-        if ch.is_ascii_alphanumeric() {
-            slug.push(ch.to_ascii_lowercase());
-            last_dash = false;
-        } else if !last_dash {
-            slug.push('-');
-            last_dash = true;
-        }
+fn write_config(state: &ProjectState, root: &Path, config: &ProjectConfig) -> Result<(), String> {
+    let raw = serde_json::to_vec_pretty(config).map_err(|error| error.to_string())?;
+    fs::write(project_config_path(root), raw)
+        .map_err(|error| format!("写入项目配置失败: {error}"))?;
+    cache_config(state, root, config);
+    Ok(())
+}
+
+fn config_stamp(root: &Path) -> Option<(SystemTime, u64)> {
+    let meta = fs::metadata(project_config_path(root)).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+fn cache_config(state: &ProjectState, root: &Path, config: &ProjectConfig) {
+    if let Ok(mut guard) = state.config_cache.lock() {
+        *guard = Some(ConfigCache {
+            root: root.to_path_buf(),
+            stamp: config_stamp(root),
+            config: config.clone(),
+        });
     }
+}
 
-    let slug = slug.trim_matches('-').to_string();
-    if slug.is_empty() {
-        "story".to_string()
-    } else {
-        slug
+fn project_config(state: &ProjectState, root: &Path) -> Result<ProjectConfig, String> {
+    let stamp = config_stamp(root);
+    if let Ok(guard) = state.config_cache.lock() {
+        if let Some(cache) = guard.as_ref() {
+            if cache.root == root && cache.stamp == stamp {
+                return Ok(cache.config.clone());
+            }
+        }
     }
+
+    let config = read_config(root)?;
+    cache_config(state, root, &config);
+    Ok(config)
 }
 
-fn make_story_folder_name(title: &str, story_id: &str) -> String {
-    let short_id = story_id.chars().take(8).collect::<String>();
-    format!("{}-{}", slugify_story_title(title), short_id)
+// Path resolution can't fail, so a config.json that turns bad after activation keeps the
+// last good layout instead of silently snapping back to the default folders.
+fn layout_config(state: &ProjectState, root: &Path) -> ProjectConfig {
+    project_config(state, root).unwrap_or_else(|_| {
+        state
+            .config_cache
+            .lock()
+            .ok()
+            .and_then(|guard| {
+                guard
+                    .as_ref()
+                    .filter(|cache| cache.root == root)
+                    .map(|cache| cache.config.clone())
+            })
+            .unwrap_or_default()
+    })
 }
 
-fn story_root(root: &Path, folder_name: &str) -> PathBuf {
-    stories_root(root).join(folder_name)
+fn project_session_path(root: &Path) -> PathBuf {
+    root.join(".session.json")
 }
 
-fn story_db_path(root: &Path, folder_name: &str) -> PathBuf {
-    story_root(root, folder_name).join("story.db")
+fn read_session(root: &Path) -> ProjectSession {
+    fs::read_to_string(project_session_path(root))
+        .ok()
+        .and_then(|raw| serde_json::from_str::<ProjectSession>(&raw).ok())
+        .unwrap_or_default()
 }
 
-fn ensure_root_layout(root: &Path) -> Result<(), String> {
-    fs::create_dir_all(stories_root(root)).map_err(|error| format!("无法创建项目目录: {error}"))?;
-    fs::create_dir_all(root.join("exports"))
-        .map_err(|error| format!("无法创建项目目录: {error}"))?;
+fn write_session(root: &Path, session: &ProjectSession) -> Result<(), String> {
+    let raw = serde_json::to_vec_pretty(session).map_err(|error| error.to_string())?;
+    fs::write(project_session_path(root), raw)
+        .map_err(|error| format!("写入项目会话记录失败: {error}"))
+}
 
-    let lock_path = root.join(".lock");
-    let lock_content = format!("pid={}\nupdated_at={}\n", std::process::id(), now_rfc3339());
-    fs::write(lock_path, lock_content).map_err(|error| format!("无法写入项目锁文件: {error}"))?;
+fn activity_log_path(root: &Path) -> PathBuf {
+    root.join("activity.log")
+}
 
-    let manifest_path = project_manifest_path(root);
-    if !manifest_path.exists() {
-        let manifest = ProjectManifest {
-            app: "takecopter".to_string(),
-            schema_version: CURRENT_SCHEMA_VERSION,
-            created_at: now_rfc3339(),
-            shared_library: default_library(),
-            stories: vec![],
-        };
-        let raw = serde_json::to_vec_pretty(&manifest).map_err(|error| error.to_string())?;
-        fs::write(manifest_path, raw).map_err(|error| format!("无法写入项目元信息: {error}"))?;
+fn rotated_activity_log_path(root: &Path) -> PathBuf {
+    root.join("activity.log.1")
+}
+
+fn append_activity(root: &Path, story_id: &str, action: &str) -> Result<(), String> {
+    let path = activity_log_path(root);
+    if fs::metadata(&path).is_ok_and(|meta| meta.len() > ACTIVITY_LOG_MAX_BYTES) {
+        fs::rename(&path, rotated_activity_log_path(root))
+            .map_err(|error| format!("轮转活动日志失败: {error}"))?;
     }
 
-    Ok(())
+    let entry = ActivityEntry {
+        timestamp: now_rfc3339(),
+        story_id: story_id.to_string(),
+        action: action.to_string(),
+    };
+    let mut line = serde_json::to_string(&entry).map_err(|error| error.to_string())?;
+    line.push('\n');
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|error| format!("写入活动日志失败: {error}"))
 }
 
-fn read_manifest(root: &Path) -> Result<ProjectManifest, String> {
-    let path = project_manifest_path(root);
-    let raw = fs::read_to_string(path).map_err(|error| format!("读取项目元信息失败: {error}"))?;
-    let manifest = match serde_json::from_str::<ProjectManifest>(&raw) {
-        Ok(current) => current,
-        Err(_) => {
-            let legacy = serde_json::from_str::<LegacyProjectManifest>(&raw)
-                .map_err(|error| format!("解析项目元信息失败: {error}"))?;
-            ProjectManifest {
-                app: legacy.app,
-                schema_version: legacy.schema_version,
-                created_at: legacy.created_at,
-                shared_library: default_library(),
-                stories: legacy
-                    .stories
-                    .into_iter()
-                    .map(|story| StoryManifestEntry {
-                        folder_name: make_story_folder_name(&story.title, &story.id),
-                        story,
-                    })
-                    .collect(),
-            }
-        }
-    };
-    if manifest.app != "takecopter" {
-        return Err("无效的项目目录来源".to_string());
-    }
-    Ok(manifest)
+fn read_activity_entries(path: &Path) -> Vec<ActivityEntry> {
+    fs::read_to_string(path)
+        .map(|raw| {
+            raw.lines()
+                .filter_map(|line| serde_json::from_str::<ActivityEntry>(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
-fn write_manifest(root: &Path, manifest: &ProjectManifest) -> Result<(), String> {
-    let raw = serde_json::to_vec_pretty(manifest).map_err(|error| error.to_string())?;
-    fs::write(project_manifest_path(root), raw)
-        .map_err(|error| format!("写入项目元信息失败: {error}"))
+fn is_valid_folder_name(name: &str) -> bool {
+    let name = name.trim();
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\'])
+        && Path::new(name).components().count() == 1
 }
 
-fn open_story_db(path: &Path) -> Result<Connection, String> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).map_err(|error| format!("无法创建故事目录: {error}"))?;
-        fs::create_dir_all(parent.join("assets").join("images"))
-            .map_err(|error| format!("无法创建故事目录: {error}"))?;
-        fs::create_dir_all(parent.join("assets").join("videos"))
-            .map_err(|error| format!("无法创建故事目录: {error}"))?;
-    }
+fn configured_folder_name(
+    config: &ProjectConfig,
+    pick: fn(&ProjectConfig) -> Option<&str>,
+    fallback: &str,
+) -> String {
+    pick(config)
+        .map(|name| name.trim().to_string())
+        .filter(|name| is_valid_folder_name(name))
+        .unwrap_or_else(|| fallback.to_string())
+}
 
-    let conn = Connection::open(path).map_err(|error| format!("故事数据库打开失败: {error}"))?;
-    conn.execute_batch(
-        "
-      CREATE TABLE IF NOT EXISTS workspace (
-        id INTEGER PRIMARY KEY,
-        settings_json TEXT NOT NULL,
-        tree_json TEXT NOT NULL,
-        library_json TEXT NOT NULL DEFAULT '{\"tags\":[],\"categories\":[]}'
-      );
-      ",
-    )
-    .map_err(|error| format!("初始化故事数据库失败: {error}"))?;
+fn stories_folder_name(config: &ProjectConfig) -> String {
+    configured_folder_name(config, |config| config.stories_dir.as_deref(), "stories")
+}
 
-    let _ = conn.execute(
-    "ALTER TABLE workspace ADD COLUMN library_json TEXT NOT NULL DEFAULT '{\"tags\":[],\"categories\":[]}'",
-    [],
-  );
+fn exports_folder_name(config: &ProjectConfig) -> String {
+    configured_folder_name(config, |config| config.exports_dir.as_deref(), "exports")
+}
 
-    Ok(conn)
+fn exports_root(state: &ProjectState, root: &Path) -> PathBuf {
+    root.join(exports_folder_name(&layout_config(state, root)))
 }
 
-fn read_workspace(path: &Path) -> Result<Workspace, String> {
-    if !path.exists() {
-        return Ok(Workspace {
-            settings: vec![],
-            tree: vec![],
-            library: default_library(),
-        });
-    }
+fn backup_excluded_paths(state: &ProjectState, root: &Path) -> Vec<String> {
+    let mut excluded = BACKUP_EXCLUDED_PATHS
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>();
+    excluded.push(exports_folder_name(&layout_config(state, root)));
+    excluded
+}
 
-    let conn = open_story_db(path)?;
-    let row = conn
-        .query_row(
-            "SELECT settings_json, tree_json, library_json FROM workspace WHERE id = 1",
-            [],
-            |row| {
-                let settings_json: String = row.get(0)?;
-                let tree_json: String = row.get(1)?;
-                let library_json: Option<String> = row.get(2)?;
-                Ok((settings_json, tree_json, library_json))
-            },
-        )
-        .optional()
-        .map_err(|error| format!("读取故事工作区失败: {error}"))?;
+fn stories_root(state: &ProjectState, root: &Path) -> PathBuf {
+    root.join(stories_folder_name(&layout_config(state, root)))
+}
 
-    if let Some((settings_json, tree_json, library_json)) = row {
-        let settings = serde_json::from_str::<Vec<serde_json::Value>>(&settings_json)
-            .map_err(|error| format!("解析故事设定失败: {error}"))?;
-        let tree = serde_json::from_str::<Vec<serde_json::Value>>(&tree_json)
-            .map_err(|error| format!("解析故事树结构失败: {error}"))?;
-        let library = library_json
-            .as_deref()
-            .map(|raw| {
-                serde_json::from_str::<SettingLibrary>(raw).unwrap_or_else(|_| default_library())
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+fn rollback_folder_moves(moved: &[(PathBuf, PathBuf)]) {
+    for (from, to) in moved.iter().rev() {
+        let _ = fs::rename(to, from);
+    }
+}
+
+// Renaming the stories/exports folder in config.json has to carry the existing data along,
+// otherwise every story would open blank from the new, empty folder.
+fn move_layout_folders(
+    root: &Path,
+    current: &ProjectConfig,
+    next: &ProjectConfig,
+) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let pairs = [
+        (stories_folder_name(current), stories_folder_name(next)),
+        (exports_folder_name(current), exports_folder_name(next)),
+    ];
+    let mut moved = Vec::new();
+    for (from_name, to_name) in pairs {
+        let from = root.join(&from_name);
+        let to = root.join(&to_name);
+        if from_name == to_name || !from.exists() {
+            continue;
+        }
+        let result = if to.exists() && !is_empty_dir(&to) {
+            Err(format!("目标目录已存在且不为空: {to_name}"))
+        } else {
+            (if to.exists() {
+                fs::remove_dir(&to)
+            } else {
+                Ok(())
             })
-            .unwrap_or_else(default_library);
-        Ok(Workspace {
-            settings,
-            tree,
-            library,
-        })
+            .and_then(|_| fs::rename(&from, &to))
+            .map_err(|error| format!("移动目录 {from_name} 失败: {error}"))
+        };
+        if let Err(error) = result {
+            rollback_folder_moves(&moved);
+            return Err(error);
+        }
+        moved.push((from, to));
+    }
+    Ok(moved)
+}
+
+fn slugify_story_title(title: &str) -> String {
+    let mut slug = String::new();
+    let mut last_dash = false;
+
+    for ch in title.chars() {
+        if ch.is_ascii_alphanumeric() {
+            slug.push(ch.to_ascii_lowercase());
+            last_dash = false;
+        } else if !last_dash {
+            slug.push('-');
+            last_dash = true;
+        }
+    }
+
+    let slug = slug.trim_matches('-').to_string();
+    if slug.is_empty() {
+        "story".to_string()
     } else {
-        Ok(Workspace {
-            settings: vec![],
-            tree: vec![],
-            library: default_library(),
-        })
+        slug
     }
 }
 
-fn write_workspace(path: &Path, workspace: &Workspace) -> Result<(), String> {
-    let conn = open_story_db(path)?;
-    let settings_json =
-        serde_json::to_string(&workspace.settings).map_err(|error| error.to_string())?;
-    let tree_json = serde_json::to_string(&workspace.tree).map_err(|error| error.to_string())?;
-    let library_json =
-        serde_json::to_string(&workspace.library).map_err(|error| error.to_string())?;
+fn make_story_folder_name(title: &str, story_id: &str) -> String {
+    let short_id = story_id.chars().take(8).collect::<String>();
+    format!("{}-{}", slugify_story_title(title), short_id)
+}
 
-    conn
-    .execute(
-      "INSERT INTO workspace (id, settings_json, tree_json, library_json) VALUES (1, ?1, ?2, ?3) ON CONFLICT(id) DO UPDATE SET settings_json = excluded.settings_json, tree_json = excluded.tree_json, library_json = excluded.library_json",
-      params![settings_json, tree_json, library_json],
-    )
-    .map_err(|error| format!("写入故事工作区失败: {error}"))?;
-    Ok(())
+fn unique_story_folder_name(manifest: &ProjectManifest, title: &str, story_id: &str) -> String {
+    unique_story_folder_name_with(manifest, title, story_id, |_| false)
 }
 
-fn find_story_entry<'a>(
-    manifest: &'a ProjectManifest,
+// Folders left on disk without a manifest entry (a crashed import, a manual copy) count as
+// taken too, so a new or renamed story never moves into someone else's files.
+fn free_story_folder_name(
+    state: &ProjectState,
+    root: &Path,
+    manifest: &ProjectManifest,
+    title: &str,
     story_id: &str,
-) -> Option<&'a StoryManifestEntry> {
-    manifest
-        .stories
-        .iter()
-        .find(|item| item.story.id == story_id)
+) -> String {
+    let current = find_story_entry(manifest, story_id).map(|entry| entry.folder_name.as_str());
+    unique_story_folder_name_with(manifest, title, story_id, |candidate| {
+        Some(candidate) != current && story_root(state, root, candidate).exists()
+    })
 }
 
-fn find_story_entry_mut<'a>(
-    manifest: &'a mut ProjectManifest,
+fn unique_story_folder_name_with(
+    manifest: &ProjectManifest,
+    title: &str,
     story_id: &str,
-) -> Option<&'a mut StoryManifestEntry> {
-    manifest
+    on_disk: impl Fn(&str) -> bool,
+) -> String {
+    let manifest_taken = manifest
         .stories
-        .iter_mut()
-        .find(|item| item.story.id == story_id)
+        .iter()
+        .filter(|entry| entry.story.id != story_id)
+        .map(|entry| entry.folder_name.as_str())
+        .collect::<HashSet<_>>();
+    let taken = |candidate: &str| manifest_taken.contains(candidate) || on_disk(candidate);
+    let slug = slugify_story_title(title);
+    let id = story_id.replace('-', "");
+    for len in 8..=id.len().max(8) {
+        let candidate = format!("{slug}-{}", id.chars().take(len).collect::<String>());
+        if !taken(&candidate) {
+            return candidate;
+        }
+    }
+
+    let mut suffix = 2;
+    loop {
+        let candidate = format!("{slug}-{id}-{suffix}");
+        if !taken(&candidate) {
+            return candidate;
+        }
+        suffix += 1;
+    }
 }
 
-fn load_project_data(root: &Path) -> Result<ProjectData, String> {
-    let mut manifest = read_manifest(root)?;
-    manifest
-        .stories
-        .sort_by(|a, b| b.story.updated_at.cmp(&a.story.updated_at));
+fn story_root(state: &ProjectState, root: &Path, folder_name: &str) -> PathBuf {
+    stories_root(state, root).join(folder_name)
+}
 
-    let mut workspaces = std::collections::HashMap::new();
-    for entry in &manifest.stories {
-        let db_path = story_db_path(root, &entry.folder_name);
-        let legacy_db_path = stories_root(root).join(&entry.story.id).join("story.db");
+fn story_asset_path(story_dir: &Path, reference: &str) -> Option<PathBuf> {
+    let relative = Path::new(reference.trim());
+    let mut components = relative.components();
+    if !matches!(components.next(), Some(std::path::Component::Normal(first)) if first == "assets")
+    {
+        return None;
+    }
+    if !components.all(|component| matches!(component, std::path::Component::Normal(_))) {
+        return None;
+    }
+    Some(story_dir.join(relative))
+}
 
-        if !db_path.exists() && legacy_db_path.exists() {
-            if let Some(parent) = db_path.parent() {
-                fs::create_dir_all(parent).map_err(|error| format!("迁移故事目录失败: {error}"))?;
+fn collect_asset_references(value: &serde_json::Value, out: &mut HashSet<String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, item) in map {
+                match item.as_str() {
+                    Some(reference)
+                        if ASSET_REFERENCE_KEYS.contains(&key.as_str())
+                            && !reference.trim().is_empty() =>
+                    {
+                        out.insert(reference.trim().to_string());
+                    }
+                    _ => collect_asset_references(item, out),
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_asset_references(item, out);
             }
-            fs::rename(&legacy_db_path, &db_path)
-                .map_err(|error| format!("迁移故事数据库失败: {error}"))?;
         }
+        _ => {}
+    }
+}
 
-        let workspace = read_workspace(&db_path)?;
-        workspaces.insert(entry.story.id.clone(), workspace);
+fn relative_asset_reference(reference: &str, folder_name: Option<&str>) -> Option<String> {
+    let normalized = reference
+        .trim()
+        .trim_start_matches("file://")
+        .replace('\\', "/");
+    if normalized.starts_with("assets/") {
+        return None;
     }
+    let start = match folder_name {
+        Some(folder_name) => {
+            let marker = format!("{folder_name}/assets/");
+            let index = normalized.rfind(&marker)?;
+            if index > 0 && !normalized[..index].ends_with('/') {
+                return None;
+            }
+            index + folder_name.len() + 1
+        }
+        None => normalized.rfind("/assets/")? + 1,
+    };
+    Some(normalized[start..].to_string())
+}
 
-    Ok(ProjectData {
-        stories: manifest
-            .stories
-            .into_iter()
-            .map(|item| item.story)
-            .collect(),
-        workspaces,
-        shared_library: manifest.shared_library,
-    })
+fn rewrite_asset_references(
+    value: &mut serde_json::Value,
+    rewrite: &dyn Fn(&str) -> Option<String>,
+) -> usize {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut changed = 0;
+            for (key, item) in map.iter_mut() {
+                if ASSET_REFERENCE_KEYS.contains(&key.as_str()) {
+                    if let Some(next) = item.as_str().and_then(rewrite) {
+                        *item = serde_json::Value::String(next);
+                        changed += 1;
+                    }
+                } else {
+                    changed += rewrite_asset_references(item, rewrite);
+                }
+            }
+            changed
+        }
+        serde_json::Value::Array(items) => items
+            .iter_mut()
+            .map(|item| rewrite_asset_references(item, rewrite))
+            .sum(),
+        _ => 0,
+    }
 }
 
-fn resolve_state_root(app: &AppHandle, state: &ProjectState) -> Result<Option<PathBuf>, String> {
-    if let Ok(guard) = state.project_root.lock() {
-        if let Some(path) = guard.as_ref() {
-            return Ok(Some(path.clone()));
+fn rewrite_workspace_asset_references(
+    workspace: &mut Workspace,
+    rewrite: &dyn Fn(&str) -> Option<String>,
+) -> usize {
+    let mut changed = workspace
+        .settings
+        .iter_mut()
+        .map(|node| rewrite_asset_references(node, rewrite))
+        .sum::<usize>();
+    for template in &mut workspace.library.templates {
+        if let Some(next) = template.preset.image_url.as_deref().and_then(rewrite) {
+            template.preset.image_url = Some(next);
+            changed += 1;
         }
     }
+    changed
+}
 
-    read_selected_root(app)
+fn sanitized_asset_reference(reference: &str) -> Option<String> {
+    let trimmed = reference.trim();
+    let lowered = trimmed.to_ascii_lowercase();
+    if trimmed.is_empty()
+        || ["http://", "https://", "data:image/", "data:video/"]
+            .iter()
+            .any(|prefix| lowered.starts_with(prefix))
+        || (story_asset_path(Path::new(""), trimmed).is_some() && !trimmed.contains('\\'))
+    {
+        return None;
+    }
+    log::warn!("导入时移除了不安全的素材路径: {trimmed}");
+    Some(String::new())
 }
 
-fn set_active_root(app: &AppHandle, state: &ProjectState, root: &Path) -> Result<(), String> {
-    if let Ok(mut guard) = state.project_root.lock() {
-        *guard = Some(root.to_path_buf());
+fn sanitize_library_asset_references(library: &mut SettingLibrary) -> usize {
+    let mut sanitized = 0;
+    for template in &mut library.templates {
+        if let Some(image_url) = template.preset.image_url.as_deref() {
+            if sanitized_asset_reference(image_url).is_some() {
+                template.preset.image_url = None;
+                sanitized += 1;
+            }
+        }
     }
-    write_selected_root(app, root)
+    sanitized
 }
 
-fn require_active_root(app: &AppHandle, state: &ProjectState) -> Result<PathBuf, String> {
-    resolve_state_root(app, state)?.ok_or_else(|| "请先创建项目目录或打开已有项目".to_string())
+fn sanitize_workspace_asset_references(workspace: &mut Workspace) -> usize {
+    workspace
+        .settings
+        .iter_mut()
+        .map(|node| rewrite_asset_references(node, &sanitized_asset_reference))
+        .sum::<usize>()
+        + sanitize_library_asset_references(&mut workspace.library)
 }
 
-fn open_path_in_file_manager(path: &Path) -> Result<(), String> {
-    #[cfg(target_os = "macos")]
-    let mut cmd = {
-        let mut c = Command::new("open");
-        c.arg(path);
-        c
-    };
+fn renamed_asset_reference(reference: &str, old_folder: &str, new_folder: &str) -> Option<String> {
+    if reference.trim_start().starts_with("assets/") {
+        return None;
+    }
+    let marker = format!("{old_folder}/assets/");
+    let index = reference
+        .rfind(&marker)
+        .or_else(|| reference.rfind(&format!("{old_folder}\\assets\\")))?;
+    let boundary = reference[..index].chars().next_back();
+    if boundary.is_some_and(|ch| ch != '/' && ch != '\\') {
+        return None;
+    }
+    let mut next = reference.to_string();
+    next.replace_range(index..index + old_folder.len(), new_folder);
+    Some(next)
+}
 
-    #[cfg(target_os = "windows")]
-    let mut cmd = {
-        let mut c = Command::new("explorer");
-        c.arg(path);
-        c
-    };
+fn migrate_story_asset_references(
+    state: &ProjectState,
+    root: &Path,
+    old_folder: &str,
+    new_folder: &str,
+) -> Result<usize, String> {
+    let db_path = story_db_path(state, root, new_folder);
+    if old_folder == new_folder || !db_path.exists() {
+        return Ok(0);
+    }
+    let mut workspace = read_workspace(&db_path)?;
+    let changed = rewrite_workspace_asset_references(&mut workspace, &|reference| {
+        renamed_asset_reference(reference, old_folder, new_folder)
+    });
+    if changed > 0 {
+        write_workspace(&db_path, &workspace)?;
+    }
+    Ok(changed)
+}
 
-    #[cfg(all(unix, not(target_os = "macos")))]
-    let mut cmd = {
-        let mut c = Command::new("xdg-open");
-        c.arg(path);
-        c
-    };
+fn workspace_asset_references(workspace: &Workspace) -> Vec<String> {
+    let mut references = HashSet::new();
+    for node in &workspace.settings {
+        collect_asset_references(node, &mut references);
+    }
+    if let Ok(templates) = serde_json::to_value(&workspace.library.templates) {
+        collect_asset_references(&templates, &mut references);
+    }
+    let mut references = references.into_iter().collect::<Vec<_>>();
+    references.sort();
+    references
+}
 
-    cmd.status()
-        .map_err(|error| format!("打开路径失败: {error}"))
-        .and_then(|status| {
-            if status.success() {
-                Ok(())
-            } else {
-                Err("打开路径失败".to_string())
+fn embed_story_assets(
+    story_dir: &Path,
+    workspace: &Workspace,
+) -> (HashMap<String, String>, Vec<String>) {
+    let folder_name = story_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+    let mut assets = HashMap::new();
+    let mut missing = Vec::new();
+    for reference in workspace_asset_references(workspace) {
+        let reference =
+            relative_asset_reference(&reference, folder_name.as_deref()).unwrap_or(reference);
+        let Some(path) = story_asset_path(story_dir, &reference) else {
+            continue;
+        };
+        match fs::read(&path) {
+            Ok(bytes) => {
+                assets.insert(reference, BASE64.encode(bytes));
             }
-        })
+            Err(error) => {
+                log::warn!("导出时跳过缺失的素材 {}: {error}", path.display());
+                missing.push(reference);
+            }
+        }
+    }
+    (assets, missing)
 }
 
-fn copy_dir_recursive(from: &Path, to: &Path) -> Result<(), String> {
-    fs::create_dir_all(to).map_err(|error| format!("创建备份目录失败: {error}"))?;
-    for entry in fs::read_dir(from).map_err(|error| format!("读取目录失败: {error}"))? {
-        let entry = entry.map_err(|error| format!("读取目录失败: {error}"))?;
-        let src = entry.path();
-        let dst = to.join(entry.file_name());
-        if src.is_dir() {
-            copy_dir_recursive(&src, &dst)?;
-        } else {
-            fs::copy(&src, &dst).map_err(|error| format!("复制文件失败: {error}"))?;
+fn collect_asset_files(dir: &Path, prefix: &str, out: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        let reference = format!("{prefix}/{name}");
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {
+                collect_asset_files(&entry.path(), &reference, out)
+            }
+            Ok(file_type) if file_type.is_file() => out.push((reference, entry.path())),
+            _ => {}
         }
     }
-    Ok(())
 }
 
-#[tauri::command]
-pub fn get_bootstrap_state(
-    app: AppHandle,
-    state: State<ProjectState>,
-) -> Result<BootstrapState, String> {
-    let default_root = default_root_path(&app)?;
-    let active_root = resolve_state_root(&app, &state)?;
+fn story_asset_files(story_dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    collect_asset_files(&story_dir.join("assets"), "assets", &mut files);
+    files.sort();
+    files
+}
 
-    Ok(BootstrapState {
-        needs_setup: active_root.is_none(),
-        default_root_path: default_root.to_string_lossy().to_string(),
-        active_root_path: active_root.map(|item| item.to_string_lossy().to_string()),
-    })
+fn store_asset_bytes(
+    story_dir: &Path,
+    reference: &str,
+    bytes: &[u8],
+) -> Result<(String, u64), String> {
+    let hash = bytes_sha256(bytes);
+    for (existing, path) in story_asset_files(story_dir) {
+        let same_size = fs::metadata(&path).is_ok_and(|meta| meta.len() == bytes.len() as u64);
+        if same_size && file_sha256(&path).as_deref() == Some(hash.as_str()) {
+            return Ok((existing, bytes.len() as u64));
+        }
+    }
+
+    let Some(mut path) = story_asset_path(story_dir, reference) else {
+        return Err(format!("素材路径无效: {reference}"));
+    };
+    let mut reference = reference.to_string();
+    let original = Path::new(&reference).to_path_buf();
+    let stem = original
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = original
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let mut suffix = 2;
+    while path.exists() {
+        let file_name = format!("{stem}-{suffix}{extension}");
+        path = path.with_file_name(&file_name);
+        reference = original
+            .with_file_name(&file_name)
+            .to_string_lossy()
+            .replace('\\', "/");
+        suffix += 1;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|error| format!("创建素材目录失败: {error}"))?;
+    }
+    fs::write(&path, bytes).map_err(|error| format!("写入素材 {reference} 失败: {error}"))?;
+    Ok((reference, 0))
 }
 
-#[tauri::command]
-pub fn pick_project_root() -> Result<Option<String>, String> {
-    let selected = rfd::FileDialog::new()
-        .set_title("选择故事项目目录")
-        .pick_folder();
-    Ok(selected.map(|path| path.to_string_lossy().to_string()))
+fn thumbnail_reference(reference: &str) -> Option<String> {
+    let relative = reference.strip_prefix("assets/images/")?;
+    let extension = Path::new(relative)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+    if !IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    Some(format!("assets/images/.thumbs/{relative}.thumb.png"))
 }
 
-#[tauri::command]
-pub fn initialize_project_root(
-    app: AppHandle,
-    state: State<ProjectState>,
-    root_path: Option<String>,
-) -> Result<(), String> {
-    let target = if let Some(path) = root_path {
-        let trimmed = path.trim();
-        if trimmed.is_empty() {
-            default_root_path(&app)?
-        } else {
-            PathBuf::from(trimmed)
+fn generate_thumbnail(story_dir: &Path, reference: &str) -> Option<String> {
+    let thumbnail = thumbnail_reference(reference)?;
+    let source = story_asset_path(story_dir, reference)?;
+    let target = story_dir.join(&thumbnail);
+    let image = match image::open(&source) {
+        Ok(image) => image,
+        Err(error) => {
+            log::warn!("无法为 {reference} 生成缩略图: {error}");
+            return None;
         }
-    } else {
-        default_root_path(&app)?
     };
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).ok()?;
+    }
+    let result = image
+        .thumbnail(THUMBNAIL_MAX_SIZE, THUMBNAIL_MAX_SIZE)
+        .save_with_format(&target, image::ImageFormat::Png);
+    match result {
+        Ok(()) => Some(thumbnail),
+        Err(error) => {
+            log::error!("写入缩略图 {thumbnail} 失败: {error}");
+            None
+        }
+    }
+}
 
-    ensure_root_layout(&target)?;
-    set_active_root(&app, &state, &target)
+fn story_asset_inventory(story_dir: &Path, workspace: &Workspace) -> AssetInventory {
+    let folder_name = story_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+    let references = workspace_asset_references(workspace)
+        .into_iter()
+        .map(|reference| {
+            relative_asset_reference(&reference, folder_name.as_deref()).unwrap_or(reference)
+        })
+        .filter(|reference| story_asset_path(story_dir, reference).is_some())
+        .collect::<HashSet<_>>();
+
+    let files = story_asset_files(story_dir)
+        .into_iter()
+        .map(|(reference, path)| {
+            let kind = if reference.starts_with("assets/images/") {
+                "image"
+            } else if reference.starts_with("assets/videos/") {
+                "video"
+            } else {
+                "other"
+            };
+            let dimensions = if kind == "image" {
+                image::image_dimensions(&path).ok()
+            } else {
+                None
+            };
+            AssetFile {
+                referenced: references.contains(&reference),
+                kind: kind.to_string(),
+                size_bytes: fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0),
+                width: dimensions.map(|(width, _)| width),
+                height: dimensions.map(|(_, height)| height),
+                reference,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut broken_references = references
+        .into_iter()
+        .filter(|reference| !files.iter().any(|file| &file.reference == reference))
+        .collect::<Vec<_>>();
+    broken_references.sort();
+    AssetInventory {
+        files,
+        broken_references,
+    }
 }
 
-#[tauri::command]
-pub fn open_project_root(
-    app: AppHandle,
-    state: State<ProjectState>,
-    root_path: String,
+fn materialize_story_assets(
+    story_dir: &Path,
+    assets: &HashMap<String, String>,
+) -> Result<(HashMap<String, String>, u64), String> {
+    let mut references = assets.keys().collect::<Vec<_>>();
+    references.sort();
+    let mut remapped = HashMap::new();
+    let mut deduplicated_bytes = 0;
+    for reference in references {
+        if story_asset_path(story_dir, reference).is_none() {
+            log::warn!("跳过路径无效的素材: {reference}");
+            continue;
+        }
+        let bytes = BASE64
+            .decode(&assets[reference])
+            .map_err(|error| format!("解析素材 {reference} 失败: {error}"))?;
+        let (stored, saved) = store_asset_bytes(story_dir, reference, &bytes)?;
+        deduplicated_bytes += saved;
+        if saved == 0 {
+            generate_thumbnail(story_dir, &stored);
+        }
+        if stored != *reference {
+            remapped.insert(reference.clone(), stored);
+        }
+    }
+    Ok((remapped, deduplicated_bytes))
+}
+
+const STORY_FOLDER_FILES: [&str; 4] = ["story.json", "settings.json", "tree.json", "library.json"];
+
+fn write_story_folder(
+    dest_dir: &Path,
+    story_dir: &Path,
+    payload: &ExportedStoryData,
 ) -> Result<(), String> {
-    let target = PathBuf::from(root_path.trim());
-    if !target.exists() {
-        return Err("项目目录不存在".to_string());
+    if dest_dir.is_dir()
+        && !dest_dir.join("story.json").exists()
+        && fs::read_dir(dest_dir)
+            .map(|mut entries| entries.next().is_some())
+            .unwrap_or(false)
+    {
+        return Err("目标目录不为空".to_string());
     }
+    fs::create_dir_all(dest_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
 
-    if !project_manifest_path(&target).exists() {
-        return Err("未找到 project.json，请先创建项目目录或选择有效项目目录".to_string());
+    let mut workspace = payload.workspace.clone();
+    let folder_name = payload.folder_name.clone();
+    rewrite_workspace_asset_references(&mut workspace, &|reference| {
+        relative_asset_reference(reference, folder_name.as_deref())
+    });
+    let meta = StoryFolderMeta {
+        app: payload.app.clone(),
+        schema_version: payload.schema_version,
+        exported_at: payload.exported_at.clone(),
+        story: payload.story.clone(),
+        workspace_updated_at: payload.workspace_updated_at.clone(),
+        folder_name: payload.folder_name.clone(),
+    };
+    let parts = [
+        serde_json::to_vec_pretty(&meta),
+        serde_json::to_vec_pretty(&workspace.settings),
+        serde_json::to_vec_pretty(&workspace.tree),
+        serde_json::to_vec_pretty(&workspace.library),
+    ];
+    for (file_name, raw) in STORY_FOLDER_FILES.iter().zip(parts) {
+        let raw = raw.map_err(|error| error.to_string())?;
+        write_file_atomically(&dest_dir.join(file_name), &raw)
+            .map_err(|error| format!("写入 {file_name} 失败: {error}"))?;
     }
 
-    ensure_root_layout(&target)?;
-    let _ = read_manifest(&target)?;
-    set_active_root(&app, &state, &target)
+    let assets_dir = dest_dir.join("assets");
+    if assets_dir.exists() {
+        fs::remove_dir_all(&assets_dir).map_err(|error| format!("清理素材目录失败: {error}"))?;
+    }
+    for (reference, path) in story_asset_files(story_dir) {
+        let target = dest_dir.join(&reference);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|error| format!("创建素材目录失败: {error}"))?;
+        }
+        fs::copy(&path, &target).map_err(|error| format!("复制素材 {reference} 失败: {error}"))?;
+    }
+    Ok(())
 }
 
-#[tauri::command]
-pub fn ensure_project(
-    app: AppHandle,
-    state: State<ProjectState>,
-) -> Result<EnsureProjectResponse, String> {
-    let root = require_active_root(&app, &state)?;
-    ensure_root_layout(&root)?;
-    let data = load_project_data(&root)?;
-    Ok(EnsureProjectResponse {
-        project_path: root.to_string_lossy().to_string(),
-        data,
+fn read_story_folder(src_dir: &Path) -> Result<ExportedStoryData, String> {
+    if let Some(missing) = STORY_FOLDER_FILES
+        .iter()
+        .find(|file_name| !src_dir.join(file_name).is_file())
+    {
+        return Err(format!("故事目录缺少文件: {missing}"));
+    }
+    fn read_part<T: serde::de::DeserializeOwned>(
+        src_dir: &Path,
+        file_name: &str,
+    ) -> Result<T, String> {
+        let raw = fs::read_to_string(src_dir.join(file_name))
+            .map_err(|error| format!("读取 {file_name} 失败: {error}"))?;
+        serde_json::from_str(&raw).map_err(|error| format!("解析 {file_name} 失败: {error}"))
+    }
+    let meta = read_part::<StoryFolderMeta>(src_dir, "story.json")?;
+
+    let mut assets = HashMap::new();
+    for (reference, path) in story_asset_files(src_dir) {
+        let bytes =
+            fs::read(&path).map_err(|error| format!("读取素材 {reference} 失败: {error}"))?;
+        assets.insert(reference, BASE64.encode(bytes));
+    }
+    Ok(ExportedStoryData {
+        app: meta.app,
+        schema_version: meta.schema_version,
+        exported_at: meta.exported_at,
+        story: meta.story,
+        workspace: Workspace {
+            settings: read_part(src_dir, "settings.json")?,
+            tree: read_part(src_dir, "tree.json")?,
+            library: read_part(src_dir, "library.json")?,
+            repaired: false,
+            recovered: false,
+        },
+        workspace_updated_at: meta.workspace_updated_at,
+        folder_name: None,
+        assets,
+        missing_assets: Vec::new(),
     })
 }
 
-#[tauri::command]
-pub fn create_story(
-    app: AppHandle,
-    state: State<ProjectState>,
-    input: CreateStoryInput,
-) -> Result<Story, String> {
-    let root = require_active_root(&app, &state)?;
-    ensure_root_layout(&root)?;
-    let mut manifest = read_manifest(&root)?;
+fn story_db_path(state: &ProjectState, root: &Path, folder_name: &str) -> PathBuf {
+    story_root(state, root, folder_name).join("story.db")
+}
 
-    let id = Uuid::new_v4().to_string();
-    let now = now_rfc3339();
-    let colors = [
-        "var(--coral-400)",
-        "var(--violet-400)",
-        "var(--teal-400)",
-        "var(--amber-400)",
-        "var(--rose-400)",
-    ];
-    let index = (Utc::now().timestamp_millis().unsigned_abs() as usize) % colors.len();
-    let story = Story {
-        id: id.clone(),
-        title: input.title,
-        description: input.description,
-        updated_at: now,
-        cover_color: colors[index].to_string(),
-    };
-    let folder_name = make_story_folder_name(&story.title, &story.id);
+fn story_history_dir(state: &ProjectState, root: &Path, folder_name: &str) -> PathBuf {
+    story_root(state, root, folder_name).join("history")
+}
 
-    let workspace = Workspace {
-        settings: vec![],
-        tree: vec![],
-        library: default_library(),
-    };
-    write_workspace(&story_db_path(&root, &folder_name), &workspace)?;
+fn is_valid_snapshot_timestamp(timestamp: &str) -> bool {
+    !timestamp.is_empty() && timestamp.chars().all(|ch| ch.is_ascii_digit() || ch == '-')
+}
 
-    manifest.stories.push(StoryManifestEntry {
-        story: story.clone(),
-        folder_name,
-    });
-    write_manifest(&root, &manifest)?;
+fn create_story_snapshot(
+    state: &ProjectState,
+    root: &Path,
+    folder_name: &str,
+    label: Option<String>,
+) -> Result<StorySnapshot, String> {
+    let history = story_history_dir(state, root, folder_name);
+    fs::create_dir_all(&history).map_err(|error| format!("创建历史目录失败: {error}"))?;
 
-    Ok(story)
+    let now = Utc::now();
+    let timestamp = now.format("%Y%m%d-%H%M%S%3f").to_string();
+    let target = history.join(format!("{timestamp}.db"));
+    let size_bytes = fs::copy(story_db_path(state, root, folder_name), &target)
+        .map_err(|error| format!("创建快照失败: {error}"))?;
+
+    let snapshot = StorySnapshot {
+        timestamp: timestamp.clone(),
+        label: label
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty()),
+        created_at: now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        size_bytes,
+    };
+    let raw = serde_json::to_vec_pretty(&snapshot).map_err(|error| error.to_string())?;
+    fs::write(history.join(format!("{timestamp}.json")), raw)
+        .map_err(|error| format!("写入快照信息失败: {error}"))?;
+    Ok(snapshot)
 }
 
-#[tauri::command]
-pub fn rename_story(
-    app: AppHandle,
-    state: State<ProjectState>,
-    story_id: String,
-    title: String,
-) -> Result<Story, String> {
-    let clean_title = title.trim();
-    if clean_title.is_empty() {
-        return Err("故事名称不能为空".to_string());
+fn list_story_snapshots(
+    state: &ProjectState,
+    root: &Path,
+    folder_name: &str,
+) -> Result<Vec<StorySnapshot>, String> {
+    let history = story_history_dir(state, root, folder_name);
+    if !history.exists() {
+        return Ok(Vec::new());
     }
 
-    let root = require_active_root(&app, &state)?;
-    let mut manifest = read_manifest(&root)?;
-    let updated_story = {
-        let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
-            return Err("故事不存在".to_string());
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(&history).map_err(|error| format!("读取历史目录失败: {error}"))?
+    {
+        let path = entry.map_err(|error| error.to_string())?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("db") {
+            continue;
+        }
+        let Some(timestamp) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
         };
-
-        let old_folder_name = entry.folder_name.clone();
-        let next_folder_name = make_story_folder_name(clean_title, &story_id);
-
-        if old_folder_name != next_folder_name {
-            let old_path = story_root(&root, &old_folder_name);
-            let next_path = story_root(&root, &next_folder_name);
-            if old_path.exists() {
-                if next_path.exists() {
-                    return Err("目标故事目录已存在，请使用其他名称".to_string());
-                }
-                fs::rename(&old_path, &next_path)
-                    .map_err(|error| format!("重命名故事目录失败: {error}"))?;
-            }
-            entry.folder_name = next_folder_name;
+        if !is_valid_snapshot_timestamp(timestamp) {
+            continue;
         }
 
-        entry.story.title = clean_title.to_string();
-        entry.story.updated_at = now_rfc3339();
-        entry.story.clone()
-    };
+        let size_bytes = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        let snapshot = fs::read_to_string(history.join(format!("{timestamp}.json")))
+            .ok()
+            .and_then(|raw| serde_json::from_str::<StorySnapshot>(&raw).ok())
+            .unwrap_or_else(|| StorySnapshot {
+                timestamp: timestamp.to_string(),
+                label: None,
+                created_at: String::new(),
+                size_bytes,
+            });
+        snapshots.push(StorySnapshot {
+            timestamp: timestamp.to_string(),
+            size_bytes,
+            ..snapshot
+        });
+    }
 
-    write_manifest(&root, &manifest)?;
-    Ok(updated_story)
+    snapshots.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(snapshots)
 }
 
-#[tauri::command]
-pub fn delete_story(
-    app: AppHandle,
-    state: State<ProjectState>,
-    story_id: String,
+fn prune_story_snapshots(
+    state: &ProjectState,
+    root: &Path,
+    folder_name: &str,
 ) -> Result<(), String> {
-    let root = require_active_root(&app, &state)?;
-    let mut manifest = read_manifest(&root)?;
+    let retention = project_config(state, root)?.snapshot_retention;
+    let history = story_history_dir(state, root, folder_name);
+    for snapshot in list_story_snapshots(state, root, folder_name)?
+        .into_iter()
+        .skip(retention)
+    {
+        let _ = fs::remove_file(history.join(format!("{}.db", snapshot.timestamp)));
+        let _ = fs::remove_file(history.join(format!("{}.json", snapshot.timestamp)));
+    }
+    Ok(())
+}
 
-    let index = manifest
-        .stories
-        .iter()
-        .position(|item| item.story.id == story_id)
-        .ok_or_else(|| "故事不存在".to_string())?;
+fn auto_snapshot_story(state: &ProjectState, root: &Path, folder_name: &str) -> Result<(), String> {
+    let interval_minutes = project_config(state, root)?.snapshot_interval_minutes;
+    if interval_minutes == 0 || !story_db_path(state, root, folder_name).exists() {
+        return Ok(());
+    }
 
-    let folder_name = manifest.stories[index].folder_name.clone();
-    let folder_path = story_root(&root, &folder_name);
-    if folder_path.exists() {
-        fs::remove_dir_all(&folder_path).map_err(|error| format!("删除故事目录失败: {error}"))?;
+    let latest = list_story_snapshots(state, root, folder_name)?
+        .into_iter()
+        .filter_map(|snapshot| chrono::DateTime::parse_from_rfc3339(&snapshot.created_at).ok())
+        .max();
+    let due = latest.is_none_or(|created_at| {
+        Utc::now().signed_duration_since(created_at)
+            >= chrono::Duration::minutes(interval_minutes as i64)
+    });
+    if due {
+        create_story_snapshot(state, root, folder_name, Some("自动快照".to_string()))?;
+        prune_story_snapshots(state, root, folder_name)?;
     }
+    Ok(())
+}
 
-    manifest.stories.remove(index);
-    write_manifest(&root, &manifest)
+fn ensure_root_writable(root: &Path) -> Result<(), String> {
+    let probe = root.join(format!(".write-test-{}", Uuid::new_v4()));
+    fs::write(&probe, b"").map_err(|error| format!("项目目录不可写: {error}"))?;
+    fs::remove_file(&probe).map_err(|error| format!("项目目录不可写: {error}"))
 }
 
-#[tauri::command]
-pub fn update_settings(
-    app: AppHandle,
-    state: State<ProjectState>,
-    story_id: String,
-    settings: Vec<serde_json::Value>,
-) -> Result<(), String> {
-    let root = require_active_root(&app, &state)?;
-    let mut manifest = read_manifest(&root)?;
-    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
-        return Err("故事不存在".to_string());
-    };
+fn lock_field(root: &Path, key: &str) -> Option<String> {
+    fs::read_to_string(root.join(".lock"))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+        .map(|value| value.trim().to_string())
+}
 
-    let current = read_workspace(&story_db_path(&root, &entry.folder_name))?;
-    let next = Workspace {
-        settings,
-        tree: current.tree,
-        library: current.library,
-    };
-    write_workspace(&story_db_path(&root, &entry.folder_name), &next)?;
+fn lock_owner_pid(root: &Path) -> Option<u32> {
+    lock_field(root, "pid").and_then(|pid| pid.parse::<u32>().ok())
+}
 
-    entry.story.updated_at = now_rfc3339();
-    write_manifest(&root, &manifest)
+#[cfg(unix)]
+fn process_is_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
 }
 
-#[tauri::command]
-pub fn update_tree(
-    app: AppHandle,
-    state: State<ProjectState>,
-    story_id: String,
-    tree: Vec<serde_json::Value>,
-) -> Result<(), String> {
-    let root = require_active_root(&app, &state)?;
-    let mut manifest = read_manifest(&root)?;
-    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
-        return Err("故事不存在".to_string());
-    };
+#[cfg(windows)]
+fn process_is_alive(pid: u32) -> bool {
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/NH"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+}
 
-    let current = read_workspace(&story_db_path(&root, &entry.folder_name))?;
-    let next = Workspace {
-        settings: current.settings,
-        tree,
-        library: current.library,
-    };
-    write_workspace(&story_db_path(&root, &entry.folder_name), &next)?;
+fn lock_heartbeat_is_fresh(root: &Path) -> bool {
+    lock_field(root, "updated_at")
+        .and_then(|raw| chrono::DateTime::parse_from_rfc3339(&raw).ok())
+        .is_some_and(|updated_at| {
+            Utc::now().signed_duration_since(updated_at).num_seconds() < LOCK_STALE_AFTER_SECS
+        })
+}
 
-    entry.story.updated_at = now_rfc3339();
-    write_manifest(&root, &manifest)
+fn lock_held_by_other_process(root: &Path) -> bool {
+    match lock_owner_pid(root) {
+        Some(pid) if pid != std::process::id() => {
+            process_is_alive(pid) && lock_heartbeat_is_fresh(root)
+        }
+        _ => false,
+    }
 }
 
-#[tauri::command]
-pub fn update_story_library(
-    app: AppHandle,
-    state: State<ProjectState>,
-    story_id: String,
-    library: SettingLibrary,
-) -> Result<(), String> {
-    let root = require_active_root(&app, &state)?;
-    let mut manifest = read_manifest(&root)?;
-    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
-        return Err("故事不存在".to_string());
+fn ensure_lock_not_foreign(root: &Path) -> Result<(), String> {
+    if lock_held_by_other_process(root) {
+        return Err("项目正被其他进程使用，无法执行此操作".to_string());
+    }
+    Ok(())
+}
+
+fn start_lock_heartbeat(state: &ProjectState, root: &Path) -> Result<(), String> {
+    write_project_lock(root)?;
+    let Ok(mut guard) = state.lock_heartbeat.lock() else {
+        return Ok(());
     };
+    if guard
+        .as_ref()
+        .is_some_and(|heartbeat| heartbeat.root == root)
+    {
+        return Ok(());
+    }
+    if let Some(heartbeat) = guard.take() {
+        heartbeat.stop.store(true, Ordering::Relaxed);
+    }
 
-    let mut current = read_workspace(&story_db_path(&root, &entry.folder_name))?;
-    current.library = library;
-    write_workspace(&story_db_path(&root, &entry.folder_name), &current)?;
+    let stop = Arc::new(AtomicBool::new(false));
+    *guard = Some(LockHeartbeat {
+        root: root.to_path_buf(),
+        stop: stop.clone(),
+    });
+    let root = root.to_path_buf();
+    thread::spawn(move || {
+        let mut next = Instant::now() + LOCK_HEARTBEAT_INTERVAL;
+        while !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_secs(1));
+            if Instant::now() < next || stop.load(Ordering::Relaxed) {
+                continue;
+            }
+            next = Instant::now() + LOCK_HEARTBEAT_INTERVAL;
+            if lock_owner_pid(&root).is_some_and(|pid| pid != std::process::id()) {
+                continue;
+            }
+            if let Err(error) = write_project_lock(&root) {
+                log::error!("更新项目锁文件失败: {error}");
+            }
+        }
+    });
+    Ok(())
+}
 
-    entry.story.updated_at = now_rfc3339();
-    write_manifest(&root, &manifest)
+fn stop_lock_heartbeat(state: &ProjectState) {
+    if let Ok(mut guard) = state.lock_heartbeat.lock() {
+        if let Some(heartbeat) = guard.take() {
+            heartbeat.stop.store(true, Ordering::Relaxed);
+        }
+    }
 }
 
-#[tauri::command]
-pub fn update_global_library(
-    app: AppHandle,
-    state: State<ProjectState>,
-    library: SettingLibrary,
-) -> Result<(), String> {
-    let root = require_active_root(&app, &state)?;
-    let mut manifest = read_manifest(&root)?;
-    manifest.shared_library = library;
-    write_manifest(&root, &manifest)
+fn write_project_lock(root: &Path) -> Result<(), String> {
+    let lock_content = format!("pid={}\nupdated_at={}\n", std::process::id(), now_rfc3339());
+    fs::write(root.join(".lock"), lock_content)
+        .map_err(|error| format!("无法写入项目锁文件: {error}"))
 }
 
-#[tauri::command]
-pub fn export_project(
-    app: AppHandle,
-    state: State<ProjectState>,
-) -> Result<ExportedProjectData, String> {
-    let root = require_active_root(&app, &state)?;
-    let data = load_project_data(&root)?;
+fn ensure_root_layout(state: &ProjectState, root: &Path) -> Result<(), String> {
+    let config = project_config(state, root)?;
+    fs::create_dir_all(root.join(stories_folder_name(&config)))
+        .map_err(|error| format!("无法创建项目目录: {error}"))?;
+    fs::create_dir_all(root.join(exports_folder_name(&config)))
+        .map_err(|error| format!("无法创建项目目录: {error}"))?;
 
-    Ok(ExportedProjectData {
-        app: "takecopter".to_string(),
-        schema_version: CURRENT_SCHEMA_VERSION,
-        exported_at: now_rfc3339(),
-        data,
-    })
+    let manifest_path = project_manifest_path(root);
+    if !manifest_path.exists() {
+        let manifest = ProjectManifest {
+            app: "takecopter".to_string(),
+            schema_version: CURRENT_SCHEMA_VERSION,
+            created_at: now_rfc3339(),
+            shared_library: default_library(),
+            stories: vec![],
+        };
+        let raw = serde_json::to_vec_pretty(&manifest).map_err(|error| error.to_string())?;
+        fs::write(manifest_path, raw).map_err(|error| format!("无法写入项目元信息: {error}"))?;
+    }
+
+    Ok(())
 }
 
-#[tauri::command]
-pub fn export_story(
-    app: AppHandle,
-    state: State<ProjectState>,
-    story_id: String,
-) -> Result<ExportedStoryData, String> {
-    let root = require_active_root(&app, &state)?;
-    let manifest = read_manifest(&root)?;
-    let Some(entry) = find_story_entry(&manifest, &story_id) else {
-        return Err("故事不存在".to_string());
+fn diff_object_keys(
+    raw: &serde_json::Value,
+    parsed: &serde_json::Value,
+    prefix: &str,
+    warning: &mut ManifestWarning,
+) {
+    let (Some(raw), Some(parsed)) = (raw.as_object(), parsed.as_object()) else {
+        return;
     };
+    for key in raw.keys().filter(|key| !parsed.contains_key(*key)) {
+        warning.unknown_fields.push(format!("{prefix}{key}"));
+    }
+    for key in parsed.keys().filter(|key| !raw.contains_key(*key)) {
+        warning.defaulted_fields.push(format!("{prefix}{key}"));
+    }
+}
 
-    let workspace = read_workspace(&story_db_path(&root, &entry.folder_name))?;
-    Ok(ExportedStoryData {
-        app: "takecopter".to_string(),
-        schema_version: CURRENT_SCHEMA_VERSION,
-        exported_at: now_rfc3339(),
-        story: entry.story.clone(),
-        workspace,
-    })
+fn collect_dropped_fields(
+    raw: &serde_json::Value,
+    parsed: &serde_json::Value,
+    path: &str,
+    dropped: &mut Vec<String>,
+) {
+    match (raw, parsed) {
+        (serde_json::Value::Object(raw), serde_json::Value::Object(parsed)) => {
+            for (key, value) in raw {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                match parsed.get(key) {
+                    Some(parsed_value) => {
+                        collect_dropped_fields(value, parsed_value, &child_path, dropped)
+                    }
+                    None => dropped.push(child_path),
+                }
+            }
+        }
+        (serde_json::Value::Array(raw), serde_json::Value::Array(parsed)) => {
+            for (value, parsed_value) in raw.iter().zip(parsed) {
+                collect_dropped_fields(value, parsed_value, &format!("{path}[]"), dropped);
+            }
+        }
+        _ => {}
+    }
 }
 
-#[tauri::command]
-pub fn export_project_to_local(
-    app: AppHandle,
-    state: State<ProjectState>,
-) -> Result<String, String> {
-    let root = require_active_root(&app, &state)?;
-    let payload = export_project(app, state)?;
-    let export_dir = root.join("exports");
-    fs::create_dir_all(&export_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
-    let file_path = export_dir.join(format!(
-        "takecopter-project-{}.json",
-        Utc::now().format("%Y%m%d-%H%M%S")
-    ));
-    let raw = serde_json::to_vec_pretty(&payload).map_err(|error| error.to_string())?;
-    fs::write(&file_path, raw).map_err(|error| format!("写入导出文件失败: {error}"))?;
-    open_path_in_file_manager(&export_dir)?;
-    Ok(export_dir.to_string_lossy().to_string())
+fn duplicate_folder_names(manifest: &ProjectManifest) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut duplicates = manifest
+        .stories
+        .iter()
+        .filter(|entry| !seen.insert(entry.folder_name.as_str()))
+        .map(|entry| entry.folder_name.clone())
+        .collect::<Vec<_>>();
+    duplicates.sort();
+    duplicates.dedup();
+    duplicates
 }
 
-#[tauri::command]
-pub fn export_story_to_local(
-    app: AppHandle,
-    state: State<ProjectState>,
-    story_id: String,
-) -> Result<String, String> {
-    let root = require_active_root(&app, &state)?;
-    let payload = export_story(app, state, story_id)?;
-    let export_dir = root.join("exports");
-    fs::create_dir_all(&export_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
-    let file_path = export_dir.join(format!(
-        "takecopter-story-{}-{}.json",
-        payload.story.id,
-        Utc::now().format("%Y%m%d-%H%M%S")
-    ));
-    let raw = serde_json::to_vec_pretty(&payload).map_err(|error| error.to_string())?;
-    fs::write(&file_path, raw).map_err(|error| format!("写入导出文件失败: {error}"))?;
-    open_path_in_file_manager(&export_dir)?;
-    Ok(export_dir.to_string_lossy().to_string())
+fn manifest_warning(
+    raw: &serde_json::Value,
+    manifest: &ProjectManifest,
+) -> Option<ManifestWarning> {
+    let parsed = serde_json::to_value(manifest).ok()?;
+    let mut warning = ManifestWarning::default();
+    diff_object_keys(raw, &parsed, "", &mut warning);
+
+    let raw_entries = raw.get("stories").and_then(|value| value.as_array());
+    let parsed_entries = parsed.get("stories").and_then(|value| value.as_array());
+    if let (Some(raw_entries), Some(parsed_entries)) = (raw_entries, parsed_entries) {
+        for (raw_entry, parsed_entry) in raw_entries.iter().zip(parsed_entries) {
+            diff_object_keys(raw_entry, parsed_entry, "stories[].", &mut warning);
+            if let (Some(raw_story), Some(parsed_story)) =
+                (raw_entry.get("story"), parsed_entry.get("story"))
+            {
+                diff_object_keys(raw_story, parsed_story, "stories[].story.", &mut warning);
+            }
+        }
+    }
+
+    warning.unknown_fields.sort();
+    warning.unknown_fields.dedup();
+    warning.defaulted_fields.sort();
+    warning.defaulted_fields.dedup();
+    warning.duplicate_folder_names = duplicate_folder_names(manifest);
+    if warning.unknown_fields.is_empty()
+        && warning.defaulted_fields.is_empty()
+        && warning.duplicate_folder_names.is_empty()
+    {
+        None
+    } else {
+        Some(warning)
+    }
 }
 
-#[tauri::command]
-pub fn backup_local_database(app: AppHandle, state: State<ProjectState>) -> Result<String, String> {
-    let root = require_active_root(&app, &state)?;
-    let export_dir = root.join("exports");
-    fs::create_dir_all(&export_dir).map_err(|error| format!("创建备份目录失败: {error}"))?;
-    let backup_dir = export_dir.join(format!("backup-{}", Utc::now().format("%Y%m%d-%H%M%S")));
-    copy_dir_recursive(&root, &backup_dir)?;
-    open_path_in_file_manager(&backup_dir)?;
-    Ok(backup_dir.to_string_lossy().to_string())
+fn manifest_stamp(root: &Path) -> Option<(SystemTime, u64)> {
+    let meta = fs::metadata(project_manifest_path(root)).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
 }
 
-#[tauri::command]
-pub fn import_project(
-    app: AppHandle,
-    state: State<ProjectState>,
-    payload: ExportedProjectData,
-) -> Result<(), String> {
-    if payload.app != "takecopter" {
-        return Err("无效的项目文件来源".to_string());
+fn cache_manifest(state: &ProjectState, root: &Path, manifest: &ProjectManifest) {
+    if let Ok(mut guard) = state.manifest_cache.lock() {
+        *guard = Some(ManifestCache {
+            root: root.to_path_buf(),
+            stamp: manifest_stamp(root),
+            manifest: manifest.clone(),
+        });
     }
-    if payload.schema_version > CURRENT_SCHEMA_VERSION {
-        return Err("项目版本过新，请升级应用后再导入".to_string());
+}
+
+fn clear_manifest_cache(state: &ProjectState) {
+    if let Ok(mut guard) = state.manifest_cache.lock() {
+        *guard = None;
     }
+}
 
-    let root = require_active_root(&app, &state)?;
-    ensure_root_layout(&root)?;
+fn read_manifest(state: &ProjectState, root: &Path) -> Result<ProjectManifest, String> {
+    let stamp = manifest_stamp(root);
+    if let Ok(guard) = state.manifest_cache.lock() {
+        if let Some(cache) = guard.as_ref() {
+            if stamp.is_some() && cache.root == root && cache.stamp == stamp {
+                return Ok(cache.manifest.clone());
+            }
+        }
+    }
 
-    let mut manifest = read_manifest(&root)?;
-    manifest.shared_library = payload.data.shared_library.clone();
-    manifest.stories = payload
-        .data
-        .stories
-        .iter()
-        .map(|story| StoryManifestEntry {
-            story: story.clone(),
-            folder_name: make_story_folder_name(&story.title, &story.id),
-        })
-        .collect();
-    write_manifest(&root, &manifest)?;
+    let (manifest, _) = read_manifest_with_warning(root)?;
+    cache_manifest(state, root, &manifest);
+    Ok(manifest)
+}
 
-    for entry in &manifest.stories {
-        let workspace = payload
-            .data
-            .workspaces
-            .get(&entry.story.id)
-            .cloned()
-            .unwrap_or(Workspace {
-                settings: vec![],
-                tree: vec![],
-                library: default_library(),
-            });
-        write_workspace(&story_db_path(&root, &entry.folder_name), &workspace)?;
+fn read_manifest_with_warning(
+    root: &Path,
+) -> Result<(ProjectManifest, Option<ManifestWarning>), String> {
+    let path = project_manifest_path(root);
+    let raw = fs::read_to_string(path).map_err(|error| format!("读取项目元信息失败: {error}"))?;
+    let value = serde_json::from_str::<serde_json::Value>(&raw)
+        .map_err(|error| format!("解析项目元信息失败: {error}"))?;
+    if value.get("app").and_then(|app| app.as_str()) != Some("takecopter") {
+        return Err("无效的项目目录来源".to_string());
+    }
+
+    let (manifest, warning) = match serde_json::from_value::<ProjectManifest>(value.clone()) {
+        Ok(current) => {
+            let warning = manifest_warning(&value, &current);
+            (current, warning)
+        }
+        Err(_) => {
+            let legacy = serde_json::from_value::<LegacyProjectManifest>(value)
+                .map_err(|error| format!("解析项目元信息失败: {error}"))?;
+            let manifest = ProjectManifest {
+                app: legacy.app,
+                schema_version: legacy.schema_version,
+                created_at: legacy.created_at,
+                shared_library: default_library(),
+                stories: legacy
+                    .stories
+                    .into_iter()
+                    .map(|story| StoryManifestEntry {
+                        folder_name: make_story_folder_name(&story.title, &story.id),
+                        story,
+                        order: None,
+                    })
+                    .collect(),
+            };
+            (manifest, None)
+        }
+    };
+    Ok((manifest, warning))
+}
+
+fn manifest_mtime(root: &Path) -> Option<SystemTime> {
+    fs::metadata(project_manifest_path(root))
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+fn record_manifest_mtime(state: &ProjectState, root: &Path) {
+    if let Ok(mut guard) = state.manifest_mtime.lock() {
+        *guard = manifest_mtime(root);
     }
+}
 
+fn ensure_manifest_unchanged(state: &ProjectState, root: &Path) -> Result<(), String> {
+    if let Ok(guard) = state.manifest_mtime.lock() {
+        if guard.is_some() && *guard != manifest_mtime(root) {
+            return Err(MANIFEST_CHANGED_EXTERNALLY.to_string());
+        }
+    }
     Ok(())
 }
 
-#[tauri::command]
-pub fn import_story(
-    app: AppHandle,
-    state: State<ProjectState>,
-    payload: ExportedStoryData,
+fn run_git(root: &Path, args: &[&str]) -> Result<std::process::Output, String> {
+    Command::new("git")
+        .args(args)
+        .current_dir(root)
+        .output()
+        .map_err(|error| format!("无法运行 git: {error}"))
+}
+
+fn git_auto_commit(root: &Path) -> Result<(), String> {
+    if !root.join(".git").exists() {
+        return Err("项目目录尚未初始化 Git 仓库".to_string());
+    }
+
+    let add = run_git(root, &["add", "-A"])?;
+    if !add.status.success() {
+        return Err(String::from_utf8_lossy(&add.stderr).trim().to_string());
+    }
+    let staged = run_git(root, &["diff", "--cached", "--quiet"])?;
+    if staged.status.success() {
+        return Ok(());
+    }
+
+    let message = format!("takecopter 自动保存 {}", now_rfc3339());
+    let commit = run_git(root, &["commit", "-q", "-m", &message])?;
+    if !commit.status.success() {
+        return Err(String::from_utf8_lossy(&commit.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+fn write_manifest(
+    state: &ProjectState,
+    root: &Path,
+    manifest: &ProjectManifest,
 ) -> Result<(), String> {
-    if payload.app != "takecopter" {
-        return Err("无效的故事文件来源".to_string());
+    if is_read_only(state) {
+        return Err(READ_ONLY_PROJECT.to_string());
     }
-    if payload.schema_version > CURRENT_SCHEMA_VERSION {
-        return Err("故事版本过新，请升级应用后再导入".to_string());
+    ensure_manifest_unchanged(state, root)?;
+
+    mark_self_write(state);
+    let raw = serde_json::to_vec_pretty(manifest).map_err(|error| error.to_string())?;
+    if let Err(error) = fs::write(project_manifest_path(root), raw) {
+        clear_manifest_cache(state);
+        return Err(format!("写入项目元信息失败: {error}"));
+    }
+    record_manifest_mtime(state, root);
+    cache_manifest(state, root, manifest);
+    if project_config(state, root).is_ok_and(|config| config.auto_commit) {
+        if let Err(error) = git_auto_commit(root) {
+            log::error!("自动提交到 Git 失败: {error}");
+        }
     }
+    Ok(())
+}
 
-    let root = require_active_root(&app, &state)?;
-    ensure_root_layout(&root)?;
+// Guards every project.json read-modify-write. Always taken after any story lock, never
+// before, so the two levels can't deadlock.
+fn manifest_write_lock(state: &ProjectState) -> MutexGuard<'_, ()> {
+    state
+        .manifest_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
 
-    let mut manifest = read_manifest(&root)?;
-    let folder_name = if let Some(existing) = find_story_entry(&manifest, &payload.story.id) {
-        existing.folder_name.clone()
-    } else {
-        make_story_folder_name(&payload.story.title, &payload.story.id)
+fn story_write_locks(
+    state: &ProjectState,
+    story_ids: impl IntoIterator<Item = String>,
+) -> Vec<Arc<Mutex<()>>> {
+    let mut story_ids = story_ids.into_iter().collect::<Vec<_>>();
+    story_ids.sort();
+    story_ids.dedup();
+    story_ids
+        .iter()
+        .map(|story_id| story_write_lock(state, story_id))
+        .collect()
+}
+
+fn story_write_lock(state: &ProjectState, story_id: &str) -> Arc<Mutex<()>> {
+    let mut locks = state
+        .story_locks
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    locks.entry(story_id.to_string()).or_default().clone()
+}
+
+fn mark_self_write(state: &ProjectState) {
+    if let Ok(mut guard) = state.last_self_write.lock() {
+        *guard = Some(Instant::now());
+    }
+}
+
+fn start_project_watcher(app: &AppHandle, state: &ProjectState, root: &Path) -> Result<(), String> {
+    let Ok(mut guard) = state.watcher.lock() else {
+        return Ok(());
     };
+    if guard.as_ref().is_some_and(|current| current.root == root) {
+        return Ok(());
+    }
+    *guard = None;
 
-    if let Some(entry) = find_story_entry_mut(&mut manifest, &payload.story.id) {
-        entry.story = payload.story.clone();
-        entry.folder_name = folder_name.clone();
-    } else {
-        manifest.stories.push(StoryManifestEntry {
-            story: payload.story.clone(),
-            folder_name: folder_name.clone(),
-        });
+    let (sender, receiver) = mpsc::channel::<notify::Result<notify::Event>>();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|error| format!("启动目录监听失败: {error}"))?;
+    watcher
+        .watch(root, RecursiveMode::NonRecursive)
+        .map_err(|error| format!("启动目录监听失败: {error}"))?;
+    let stories = stories_root(state, root);
+    if stories.exists() {
+        watcher
+            .watch(&stories, RecursiveMode::Recursive)
+            .map_err(|error| format!("启动目录监听失败: {error}"))?;
     }
 
-    write_manifest(&root, &manifest)?;
-    write_workspace(&story_db_path(&root, &folder_name), &payload.workspace)
+    let app = app.clone();
+    let watch_root = root.to_path_buf();
+    let last_self_write = state.last_self_write.clone();
+    thread::spawn(move || {
+        watch_project_changes(app, watch_root, stories, receiver, last_self_write)
+    });
+
+    *guard = Some(ProjectWatcher {
+        root: root.to_path_buf(),
+        _watcher: watcher,
+    });
+    Ok(())
 }
 
-#[tauri::command]
-pub fn open_story_folder(
+fn stop_project_watcher(state: &ProjectState) {
+    if let Ok(mut guard) = state.watcher.lock() {
+        *guard = None;
+    }
+}
+
+fn watch_project_changes(
     app: AppHandle,
-    state: State<ProjectState>,
-    story_id: String,
-) -> Result<(), String> {
-    let root = require_active_root(&app, &state)?;
-    let manifest = read_manifest(&root)?;
+    root: PathBuf,
+    stories: PathBuf,
+    receiver: mpsc::Receiver<notify::Result<notify::Event>>,
+    last_self_write: Arc<Mutex<Option<Instant>>>,
+) {
+    while let Ok(first) = receiver.recv() {
+        let mut events = vec![first];
+        while let Ok(event) = receiver.recv_timeout(WATCH_DEBOUNCE) {
+            events.push(event);
+        }
+
+        let suppressed = last_self_write
+            .lock()
+            .ok()
+            .and_then(|guard| *guard)
+            .is_some_and(|at| at.elapsed() < SELF_WRITE_SUPPRESSION);
+        if suppressed {
+            continue;
+        }
+
+        let mut project_changed = false;
+        let mut changed_folders = HashSet::new();
+        for event in events.into_iter().flatten() {
+            for path in event.paths {
+                let file_name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                if file_name == "project.json" && path.parent() == Some(root.as_path()) {
+                    project_changed = true;
+                } else if file_name.starts_with("story.db") {
+                    if let Some(folder) = path
+                        .strip_prefix(&stories)
+                        .ok()
+                        .and_then(|relative| relative.components().next())
+                    {
+                        changed_folders.insert(folder.as_os_str().to_string_lossy().to_string());
+                    }
+                }
+            }
+        }
+
+        if project_changed {
+            let _ = app.emit(
+                "project-changed",
+                ProjectChangedEvent {
+                    project_path: root.to_string_lossy().to_string(),
+                },
+            );
+        }
+        if changed_folders.is_empty() {
+            continue;
+        }
+        let state = app.state::<ProjectState>();
+        let Ok(manifest) = read_manifest(&state, &root) else {
+            continue;
+        };
+        for entry in manifest.stories {
+            if changed_folders.contains(&entry.folder_name) {
+                let _ = app.emit(
+                    "story-changed",
+                    StoryChangedEvent {
+                        story_id: entry.story.id,
+                    },
+                );
+            }
+        }
+    }
+}
+
+fn open_story_db(path: &Path) -> Result<Connection, String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|error| format!("无法创建故事目录: {error}"))?;
+        fs::create_dir_all(parent.join("assets").join("images"))
+            .map_err(|error| format!("无法创建故事目录: {error}"))?;
+        fs::create_dir_all(parent.join("assets").join("videos"))
+            .map_err(|error| format!("无法创建故事目录: {error}"))?;
+    }
+
+    let conn = Connection::open(path).map_err(|error| format!("故事数据库打开失败: {error}"))?;
+    let library_default = serde_json::to_string(&default_library())
+        .map_err(|error| error.to_string())?
+        .replace('\'', "''");
+    conn.execute_batch(&format!(
+        "
+      CREATE TABLE IF NOT EXISTS workspace (
+        id INTEGER PRIMARY KEY,
+        settings_json TEXT NOT NULL,
+        tree_json TEXT NOT NULL,
+        library_json TEXT NOT NULL DEFAULT '{library_default}',
+        touched_at TEXT
+      );
+      ",
+    ))
+    .map_err(|error| format!("初始化故事数据库失败: {error}"))?;
+
+    let _ = conn.execute(
+        &format!(
+            "ALTER TABLE workspace ADD COLUMN library_json TEXT NOT NULL DEFAULT '{library_default}'"
+        ),
+        [],
+    );
+    let _ = conn.execute("ALTER TABLE workspace ADD COLUMN touched_at TEXT", []);
+    conn.execute_batch(
+        "
+      CREATE TRIGGER IF NOT EXISTS workspace_touched_at
+      AFTER UPDATE OF settings_json, tree_json, library_json ON workspace
+      WHEN NEW.touched_at IS OLD.touched_at
+      BEGIN
+        UPDATE workspace SET touched_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id;
+      END;
+      ",
+    )
+    .map_err(|error| format!("初始化故事数据库失败: {error}"))?;
+    let _ = conn.execute_batch(SEARCH_INDEX_SCHEMA);
+
+    Ok(conn)
+}
+
+// Read-only projects (another instance holds the lock) must not touch story.db at all: no
+// schema setup, no migrations, no index rebuilds, no quarantine.
+fn open_story_db_read_only(path: &Path) -> Result<Connection, String> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|error| format!("故事数据库打开失败: {error}"))
+}
+
+fn open_story_db_as(path: &Path, read_only: bool) -> Result<Connection, String> {
+    if read_only {
+        open_story_db_read_only(path)
+    } else {
+        open_story_db(path)
+    }
+}
+
+// Older databases opened read-only never got the migrated columns, so they read as NULL.
+fn workspace_row_query(conn: &Connection, columns: &[&str]) -> Result<Option<String>, String> {
+    let existing = conn
+        .prepare("SELECT name FROM pragma_table_info('workspace')")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<HashSet<_>>>()
+        })
+        .map_err(|error| format!("读取故事工作区失败: {error}"))?;
+    if existing.is_empty() {
+        return Ok(None);
+    }
+    let selected = columns
+        .iter()
+        .map(|column| {
+            if existing.contains(*column) {
+                *column
+            } else {
+                "NULL"
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(Some(format!(
+        "SELECT {} FROM workspace WHERE id = 1",
+        selected.join(", ")
+    )))
+}
+
+fn workspace_updated_at(path: &Path, read_only: bool) -> Option<String> {
+    read_workspace_touched_at(path, read_only).or_else(|| {
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+        Some(
+            chrono::DateTime::<Utc>::from(modified)
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        )
+    })
+}
+
+fn read_workspace_touched_at(path: &Path, read_only: bool) -> Option<String> {
+    if !path.exists() {
+        return None;
+    }
+    let conn = open_story_db_as(path, read_only).ok()?;
+    let query = workspace_row_query(&conn, &["touched_at"]).ok()??;
+    conn.query_row(&query, [], |row| row.get::<_, Option<String>>(0))
+        .optional()
+        .ok()
+        .flatten()
+        .flatten()
+}
+
+fn normalize_node_ids(nodes: &mut [serde_json::Value]) -> usize {
+    let mut repaired = 0;
+    for node in nodes {
+        let Some(object) = node.as_object_mut() else {
+            continue;
+        };
+        let has_id = object
+            .get("id")
+            .and_then(|value| value.as_str())
+            .is_some_and(|id| !id.trim().is_empty());
+        if !has_id {
+            object.insert(
+                "id".to_string(),
+                serde_json::Value::String(Uuid::new_v4().to_string()),
+            );
+            repaired += 1;
+        }
+        if let Some(children) = object
+            .get_mut("children")
+            .and_then(|value| value.as_array_mut())
+        {
+            repaired += normalize_node_ids(children);
+        }
+    }
+    repaired
+}
+
+fn is_corruption_error(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::NotADatabase | rusqlite::ErrorCode::DatabaseCorrupt)
+    )
+}
+
+fn story_db_is_corrupt(path: &Path) -> bool {
+    let conn = match Connection::open(path) {
+        Ok(conn) => conn,
+        Err(error) => return is_corruption_error(&error),
+    };
+    match conn.query_row("PRAGMA quick_check", [], |row| row.get::<_, String>(0)) {
+        Ok(result) => result != "ok",
+        Err(error) => is_corruption_error(&error),
+    }
+}
+
+fn read_story_workspace(state: &ProjectState, path: &Path) -> Result<Workspace, String> {
+    if is_read_only(state) {
+        read_workspace_from_db(path, true)
+    } else {
+        read_workspace(path)
+    }
+}
+
+fn read_workspace(path: &Path) -> Result<Workspace, String> {
+    let error = match read_workspace_from_db(path, false) {
+        Ok(workspace) => return Ok(workspace),
+        Err(error) => error,
+    };
+    if !path.is_file() || !story_db_is_corrupt(path) {
+        return Err(error);
+    }
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "story.db".to_string());
+    let quarantined = path.with_file_name(format!(
+        "{file_name}.corrupt-{}",
+        Utc::now().format("%Y%m%d%H%M%S")
+    ));
+    fs::rename(path, &quarantined)
+        .map_err(|rename_error| format!("{error}; 隔离损坏的故事数据库失败: {rename_error}"))?;
+    log::warn!(
+        "故事数据库已损坏，已移动到 {}: {error}",
+        quarantined.display()
+    );
+
+    Ok(Workspace {
+        settings: vec![],
+        tree: vec![],
+        library: default_library(),
+        repaired: false,
+        recovered: true,
+    })
+}
+
+fn read_library_from_db(path: &Path, read_only: bool) -> Result<SettingLibrary, String> {
+    if !path.exists() {
+        return Ok(default_library());
+    }
+    let conn = open_story_db_as(path, read_only)?;
+    let library_json = match workspace_row_query(&conn, &["library_json"])? {
+        Some(query) => conn
+            .query_row(&query, [], |row| row.get::<_, Option<String>>(0))
+            .optional()
+            .map_err(|error| format!("读取故事设定库失败: {error}"))?
+            .flatten(),
+        None => None,
+    };
+    let mut library = library_json
+        .as_deref()
+        .and_then(|raw| serde_json::from_str::<SettingLibrary>(raw).ok())
+        .unwrap_or_else(default_library);
+    normalize_categories(&mut library.categories);
+    Ok(library)
+}
+
+fn read_workspace_from_db(path: &Path, read_only: bool) -> Result<Workspace, String> {
+    if !path.exists() {
+        return Ok(Workspace {
+            settings: vec![],
+            tree: vec![],
+            library: default_library(),
+            repaired: false,
+            recovered: false,
+        });
+    }
+
+    let conn = open_story_db_as(path, read_only)?;
+    let row = match workspace_row_query(&conn, &["settings_json", "tree_json", "library_json"])? {
+        Some(query) => conn
+            .query_row(&query, [], |row| {
+                let settings_json: String = row.get(0)?;
+                let tree_json: String = row.get(1)?;
+                let library_json: Option<String> = row.get(2)?;
+                Ok((settings_json, tree_json, library_json))
+            })
+            .optional()
+            .map_err(|error| format!("读取故事工作区失败: {error}"))?,
+        None => None,
+    };
+
+    if let Some((settings_json, tree_json, library_json)) = row {
+        let settings = serde_json::from_str::<Vec<serde_json::Value>>(&settings_json)
+            .map_err(|error| format!("解析故事设定失败: {error}"))?;
+        let tree = serde_json::from_str::<Vec<serde_json::Value>>(&tree_json)
+            .map_err(|error| format!("解析故事树结构失败: {error}"))?;
+        let library = library_json
+            .as_deref()
+            .map(|raw| {
+                serde_json::from_str::<SettingLibrary>(raw).unwrap_or_else(|_| default_library())
+            })
+            .unwrap_or_else(default_library);
+        let mut workspace = Workspace {
+            settings,
+            tree,
+            library,
+            repaired: false,
+            recovered: false,
+        };
+        let repaired =
+            normalize_node_ids(&mut workspace.settings) + normalize_node_ids(&mut workspace.tree);
+        if repaired > 0 {
+            log::info!(
+                "已为 {repaired} 个缺少 id 的节点生成 id: {}",
+                path.display()
+            );
+            workspace.repaired = true;
+        }
+        if normalize_categories(&mut workspace.library.categories) {
+            workspace.repaired = true;
+        }
+        Ok(workspace)
+    } else {
+        Ok(Workspace {
+            settings: vec![],
+            tree: vec![],
+            library: default_library(),
+            repaired: false,
+            recovered: false,
+        })
+    }
+}
+
+fn write_workspace(path: &Path, workspace: &Workspace) -> Result<(), String> {
+    write_workspace_touched_at(path, workspace, &now_rfc3339())
+}
+
+const NODE_STAMP_IGNORED_KEYS: [&str; 3] = ["updatedAt", "children", "isSelected"];
+
+fn index_nodes_by_id<'a>(
+    nodes: &'a [serde_json::Value],
+    index: &mut HashMap<String, &'a serde_json::Value>,
+) {
+    for node in nodes {
+        if let Some(id) = node_id(node) {
+            index.insert(id.to_string(), node);
+        }
+        index_nodes_by_id(tree_children(node), index);
+    }
+}
+
+fn node_content_eq(
+    a: &serde_json::Map<String, serde_json::Value>,
+    b: &serde_json::Map<String, serde_json::Value>,
+) -> bool {
+    let relevant = |object: &serde_json::Map<String, serde_json::Value>| {
+        object
+            .iter()
+            .filter(|(key, _)| !NODE_STAMP_IGNORED_KEYS.contains(&key.as_str()))
+            .count()
+    };
+    relevant(a) == relevant(b)
+        && a.iter()
+            .filter(|(key, _)| !NODE_STAMP_IGNORED_KEYS.contains(&key.as_str()))
+            .all(|(key, value)| b.get(key) == Some(value))
+}
+
+fn stamp_node_updates(nodes: &mut [serde_json::Value], previous: &[serde_json::Value], now: &str) {
+    let mut index = HashMap::new();
+    index_nodes_by_id(previous, &mut index);
+    stamp_nodes_with_index(nodes, &index, now);
+}
+
+fn stamp_nodes_with_index(
+    nodes: &mut [serde_json::Value],
+    previous: &HashMap<String, &serde_json::Value>,
+    now: &str,
+) {
+    for node in nodes {
+        let prior = node_id(node).and_then(|id| previous.get(id).copied());
+        let Some(object) = node.as_object_mut() else {
+            continue;
+        };
+
+        let stamp = match prior {
+            Some(prior)
+                if prior
+                    .as_object()
+                    .is_some_and(|prior| node_content_eq(prior, object)) =>
+            {
+                prior.get("updatedAt").cloned()
+            }
+            Some(_) => Some(serde_json::Value::String(now.to_string())),
+            None if object.contains_key("updatedAt") => None,
+            None => Some(serde_json::Value::String(now.to_string())),
+        };
+        if let Some(stamp) = stamp {
+            object.insert("updatedAt".to_string(), stamp);
+        }
+
+        if let Some(children) = object
+            .get_mut("children")
+            .and_then(|value| value.as_array_mut())
+        {
+            stamp_nodes_with_index(children, previous, now);
+        }
+    }
+}
+
+fn node_body_text(node: &serde_json::Value) -> String {
+    let mut values = Vec::new();
+    if let Some(map) = node.as_object() {
+        for (key, value) in map {
+            if key != "title" && key != "children" && key != "updatedAt" && !is_id_key(key) {
+                collect_text_values(value, &mut values);
+            }
+        }
+    }
+    values.join("\n")
+}
+
+fn index_search_nodes(
+    statement: &mut rusqlite::Statement<'_>,
+    nodes: &[serde_json::Value],
+    kind: &str,
+) -> rusqlite::Result<()> {
+    for node in nodes {
+        if let Some(id) = node_id(node) {
+            statement.execute(params![
+                id,
+                kind,
+                node_title(node).unwrap_or_default(),
+                node_body_text(node)
+            ])?;
+        }
+        index_search_nodes(statement, tree_children(node), kind)?;
+    }
+    Ok(())
+}
+
+fn rebuild_search_index(
+    conn: &Connection,
+    settings: &[serde_json::Value],
+    tree: &[serde_json::Value],
+) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM search_index", [])?;
+    let mut statement = conn
+        .prepare("INSERT INTO search_index (node_id, kind, title, body) VALUES (?1, ?2, ?3, ?4)")?;
+    index_search_nodes(&mut statement, settings, "setting")?;
+    index_search_nodes(&mut statement, tree, "tree")
+}
+
+fn search_snippet(title: &str, body: &str, query: &str) -> String {
+    let query = query.to_lowercase();
+    for text in [body, title] {
+        let chars = text.chars().collect::<Vec<_>>();
+        let lower = chars
+            .iter()
+            .map(|ch| ch.to_lowercase().next().unwrap_or(*ch))
+            .collect::<Vec<_>>();
+        let needle = query.chars().collect::<Vec<_>>();
+        if needle.is_empty() || needle.len() > lower.len() {
+            continue;
+        }
+        if let Some(position) = lower
+            .windows(needle.len())
+            .position(|window| window == needle)
+        {
+            let start = position.saturating_sub(20);
+            let end = (position + needle.len() + 40).min(chars.len());
+            let mut snippet = chars[start..end].iter().collect::<String>();
+            if start > 0 {
+                snippet.insert(0, '…');
+            }
+            if end < chars.len() {
+                snippet.push('…');
+            }
+            return snippet;
+        }
+    }
+    body.chars().take(60).collect()
+}
+
+type SearchRow = (String, String, String, String, f64);
+
+fn search_story_index(
+    path: &Path,
+    query: &str,
+    limit: usize,
+    read_only: bool,
+) -> Result<Vec<SearchRow>, String> {
+    let stored = open_story_db_as(path, read_only)?;
+    let indexed = stored
+        .query_row("SELECT count(*) FROM search_index", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .or_else(|error| if read_only { Ok(0) } else { Err(error) })
+        .map_err(|error| format!("读取搜索索引失败: {error}"))?;
+    // A read-only project can't persist a rebuilt index, so it searches a throwaway copy.
+    let conn = if indexed == 0 && read_only {
+        let memory =
+            Connection::open_in_memory().map_err(|error| format!("重建搜索索引失败: {error}"))?;
+        memory
+            .execute_batch(SEARCH_INDEX_SCHEMA)
+            .map_err(|error| format!("重建搜索索引失败: {error}"))?;
+        memory
+    } else {
+        stored
+    };
+    if indexed == 0 {
+        let workspace = read_workspace_from_db(path, read_only)?;
+        rebuild_search_index(&conn, &workspace.settings, &workspace.tree)
+            .map_err(|error| format!("重建搜索索引失败: {error}"))?;
+    }
+
+    let map_row = |row: &rusqlite::Row<'_>| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, f64>(4)?,
+        ))
+    };
+    let rows = if query.chars().count() >= 3 {
+        let phrase = format!("\"{}\"", query.replace('"', "\"\""));
+        let mut statement = conn
+            .prepare("SELECT node_id, kind, title, body, bm25(search_index) FROM search_index WHERE search_index MATCH ?1 ORDER BY bm25(search_index) LIMIT ?2")
+            .map_err(|error| format!("搜索失败: {error}"))?;
+        let rows = statement
+            .query_map(params![phrase, limit as i64], map_row)
+            .map_err(|error| format!("搜索失败: {error}"))?
+            .collect::<rusqlite::Result<Vec<_>>>();
+        rows
+    } else {
+        let pattern = format!("%{query}%");
+        let mut statement = conn
+            .prepare("SELECT node_id, kind, title, body, 0.0 FROM search_index WHERE title LIKE ?1 OR body LIKE ?1 LIMIT ?2")
+            .map_err(|error| format!("搜索失败: {error}"))?;
+        let rows = statement
+            .query_map(params![pattern, limit as i64], map_row)
+            .map_err(|error| format!("搜索失败: {error}"))?
+            .collect::<rusqlite::Result<Vec<_>>>();
+        rows
+    };
+    rows.map_err(|error| format!("搜索失败: {error}"))
+}
+
+fn write_workspace_touched_at(
+    path: &Path,
+    workspace: &Workspace,
+    touched_at: &str,
+) -> Result<(), String> {
+    let conn = open_story_db(path)?;
+    let previous = conn
+        .query_row(
+            "SELECT settings_json, tree_json FROM workspace WHERE id = 1",
+            [],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()
+        .map_err(|error| format!("读取故事工作区失败: {error}"))?;
+    let (previous_settings, previous_tree) = previous
+        .map(|(settings_json, tree_json)| {
+            (
+                serde_json::from_str::<Vec<serde_json::Value>>(&settings_json).unwrap_or_default(),
+                serde_json::from_str::<Vec<serde_json::Value>>(&tree_json).unwrap_or_default(),
+            )
+        })
+        .unwrap_or_default();
+
+    let mut settings = workspace.settings.clone();
+    stamp_node_updates(&mut settings, &previous_settings, touched_at);
+    let mut tree = workspace.tree.clone();
+    stamp_node_updates(&mut tree, &previous_tree, touched_at);
+
+    let settings_json = serde_json::to_string(&settings).map_err(|error| error.to_string())?;
+    let tree_json = serde_json::to_string(&tree).map_err(|error| error.to_string())?;
+    let library_json =
+        serde_json::to_string(&workspace.library).map_err(|error| error.to_string())?;
+
+    conn
+    .execute(
+      "INSERT INTO workspace (id, settings_json, tree_json, library_json, touched_at) VALUES (1, ?1, ?2, ?3, ?4) ON CONFLICT(id) DO UPDATE SET settings_json = excluded.settings_json, tree_json = excluded.tree_json, library_json = excluded.library_json, touched_at = excluded.touched_at",
+      params![settings_json, tree_json, library_json, touched_at],
+    )
+    .map_err(|error| format!("写入故事工作区失败: {error}"))?;
+    if let Err(error) = rebuild_search_index(&conn, &settings, &tree) {
+        log::error!("更新搜索索引失败: {error}");
+    }
+    Ok(())
+}
+
+fn clean_story_title(title: &str) -> Result<String, String> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("故事名称不能为空".to_string());
+    }
+    if title.chars().count() > STORY_TITLE_MAX_CHARS {
+        return Err(format!("故事名称不能超过 {STORY_TITLE_MAX_CHARS} 个字符"));
+    }
+    Ok(title.to_string())
+}
+
+fn cover_palette(state: &ProjectState, root: &Path) -> Vec<String> {
+    let mut palette = PALETTE_COLORS
+        .iter()
+        .map(|color| color.to_string())
+        .collect::<Vec<_>>();
+    let custom_colors = project_config(state, root)
+        .map(|config| config.custom_cover_colors)
+        .unwrap_or_default();
+    for color in custom_colors {
+        let color = color.trim().to_string();
+        if is_valid_color(&color) && !palette.contains(&color) {
+            palette.push(color);
+        }
+    }
+    palette
+}
+
+fn extension_allowed(allowed: &[String], extension: &str) -> bool {
+    allowed.iter().any(|item| {
+        item.trim()
+            .trim_start_matches('.')
+            .eq_ignore_ascii_case(extension)
+    })
+}
+
+fn default_cover_color(story_id: &str) -> &'static str {
+    let digest = Sha256::digest(story_id.as_bytes());
+    let hash = u64::from_be_bytes(digest[..8].try_into().unwrap_or_default());
+    PALETTE_COLORS[(hash % PALETTE_COLORS.len() as u64) as usize]
+}
+
+fn validate_cover_color(state: &ProjectState, root: &Path, color: &str) -> Result<String, String> {
+    let color = color.trim().to_string();
+    if !cover_palette(state, root).contains(&color) {
+        return Err(format!("封面颜色不在调色板中: {color}"));
+    }
+    Ok(color)
+}
+
+fn parse_timestamp(raw: &str) -> Option<chrono::DateTime<Utc>> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return None;
+    }
+    if let Ok(parsed) = chrono::DateTime::parse_from_rfc3339(raw) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    if let Ok(parsed) = chrono::DateTime::parse_from_rfc2822(raw) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S%.f%:z", "%Y-%m-%d %H:%M:%S%.f%z"] {
+        if let Ok(parsed) = chrono::DateTime::parse_from_str(raw, format) {
+            return Some(parsed.with_timezone(&Utc));
+        }
+    }
+    let naive = raw.trim_end_matches(['Z', 'z']);
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y/%m/%d %H:%M:%S",
+    ] {
+        if let Ok(parsed) = chrono::NaiveDateTime::parse_from_str(naive, format) {
+            return Some(parsed.and_utc());
+        }
+    }
+    for format in ["%Y-%m-%d", "%Y/%m/%d"] {
+        if let Ok(parsed) = chrono::NaiveDate::parse_from_str(naive, format) {
+            return parsed.and_hms_opt(0, 0, 0).map(|parsed| parsed.and_utc());
+        }
+    }
+    // Bare integers are Unix epochs; anything past year 2286 in seconds is taken as millis.
+    let epoch = raw.parse::<i64>().ok()?;
+    if epoch.abs() >= 10_000_000_000 {
+        chrono::DateTime::from_timestamp_millis(epoch)
+    } else {
+        chrono::DateTime::from_timestamp(epoch, 0)
+    }
+}
+
+fn normalize_import_timestamp(raw: &str, invalid: &mut Vec<String>) -> String {
+    match parse_timestamp(raw) {
+        Some(parsed) => parsed.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        None => {
+            invalid.push(raw.to_string());
+            now_rfc3339()
+        }
+    }
+}
+
+fn validate_imported_story(story: &Story) -> Result<(), String> {
+    if story.id.trim().is_empty() {
+        return Err("导入的故事缺少 ID".to_string());
+    }
+    if Uuid::parse_str(&story.id).is_err() {
+        return Err(format!("导入的故事 ID 格式无效: {}", story.id));
+    }
+    if story.title.trim().is_empty() {
+        return Err(format!("导入的故事名称不能为空: {}", story.id));
+    }
+    Ok(())
+}
+
+fn is_valid_color(value: &str) -> bool {
+    let value = value.trim();
+    if let Some(name) = value
+        .strip_prefix("var(--")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        return !name.is_empty()
+            && name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_');
+    }
+    if let Some(hex) = value.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|ch| ch.is_ascii_hexdigit());
+    }
+    let args = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))
+        .and_then(|rest| rest.strip_suffix(')'));
+    if let Some(args) = args {
+        let parts = args
+            .split([',', ' ', '/'])
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        return matches!(parts.len(), 3 | 4)
+            && parts.iter().all(|part| {
+                part.trim_end_matches('%')
+                    .parse::<f64>()
+                    .map(|number| number >= 0.0)
+                    .unwrap_or(false)
+            });
+    }
+    false
+}
+
+fn validate_custom_field_value(
+    name: &str,
+    kind: &CustomFieldKind,
+    value: &str,
+) -> Result<(), String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(());
+    }
+    match kind {
+        CustomFieldKind::Text | CustomFieldKind::Other(_) => Ok(()),
+        CustomFieldKind::Number => value
+            .parse::<f64>()
+            .map(|_| ())
+            .map_err(|_| format!("属性「{name}」需要填写数字: {value}")),
+        CustomFieldKind::Date => {
+            if chrono::DateTime::parse_from_rfc3339(value).is_ok()
+                || chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+            {
+                Ok(())
+            } else {
+                Err(format!("属性「{name}」需要填写日期: {value}"))
+            }
+        }
+    }
+}
+
+fn validate_settings(settings: &[serde_json::Value]) -> Result<(), String> {
+    for node in settings {
+        let Some(fields) = node.get("customFields").and_then(|value| value.as_array()) else {
+            continue;
+        };
+        for field in fields {
+            let kind = field
+                .get("kind")
+                .cloned()
+                .and_then(|value| serde_json::from_value::<CustomFieldKind>(value).ok())
+                .unwrap_or_default();
+            let name = field
+                .get("name")
+                .and_then(|value| value.as_str())
+                .unwrap_or("");
+            let value = field
+                .get("value")
+                .and_then(|value| value.as_str())
+                .unwrap_or("");
+            validate_custom_field_value(name, &kind, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn duplicate_tag_names(tags: &[SettingTag]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for tag in tags {
+        let key = tag.name.trim().to_lowercase();
+        if !seen.insert(key) && !duplicates.contains(&tag.name) {
+            duplicates.push(tag.name.clone());
+        }
+    }
+    duplicates
+}
+
+fn duplicate_template_ids(templates: &[SettingTemplate]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for template in templates {
+        if !seen.insert(template.id.as_str()) && !duplicates.contains(&template.id) {
+            duplicates.push(template.id.clone());
+        }
+    }
+    duplicates
+}
+
+// Categories compare trimmed and case-folded everywhere ("Magic" and "magic " are one
+// category); the first spelling seen is the one kept.
+fn category_key(category: &str) -> String {
+    category.trim().to_lowercase()
+}
+
+fn normalize_categories(categories: &mut Vec<String>) -> bool {
+    let mut seen = HashSet::new();
+    let normalized = categories
+        .iter()
+        .map(|category| category.trim())
+        .filter(|category| !category.is_empty() && seen.insert(category_key(category)))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if normalized == *categories {
+        return false;
+    }
+    *categories = normalized;
+    true
+}
+
+fn dedupe_library_entries(library: &mut SettingLibrary) -> bool {
+    let mut changed = normalize_categories(&mut library.categories);
+
+    let mut seen_tags = HashSet::new();
+    let before = library.tags.len();
+    library
+        .tags
+        .retain(|tag| seen_tags.insert(tag.name.trim().to_lowercase()));
+    changed |= library.tags.len() != before;
+
+    let mut seen_templates = HashSet::new();
+    for template in &mut library.templates {
+        if !seen_templates.insert(template.id.clone()) {
+            template.id = Uuid::new_v4().to_string();
+            seen_templates.insert(template.id.clone());
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+fn validate_template_types(
+    state: &ProjectState,
+    root: &Path,
+    library: &SettingLibrary,
+) -> Result<(), String> {
+    let custom_types = project_config(state, root)?.custom_template_types;
+    for template in &library.templates {
+        let template_type = template.preset.r#type.trim();
+        if !BUILTIN_TEMPLATE_TYPES.contains(&template_type)
+            && !custom_types.iter().any(|item| item.trim() == template_type)
+        {
+            return Err(format!(
+                "模版「{}」的类型无效: {}",
+                template.name, template.preset.r#type
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_library(library: &SettingLibrary) -> Result<(), String> {
+    let duplicate_tags = duplicate_tag_names(&library.tags);
+    if !duplicate_tags.is_empty() {
+        return Err(format!("标签名称重复: {}", duplicate_tags.join("、")));
+    }
+    let duplicate_templates = duplicate_template_ids(&library.templates);
+    if !duplicate_templates.is_empty() {
+        return Err(format!("模版 ID 重复: {}", duplicate_templates.join("、")));
+    }
+    for tag in &library.tags {
+        if !is_valid_color(&tag.color) {
+            return Err(format!("标签「{}」的颜色无效: {}", tag.name, tag.color));
+        }
+    }
+    for template in &library.templates {
+        for tag in &template.preset.tags {
+            if !is_valid_color(&tag.color) {
+                return Err(format!(
+                    "模版「{}」中标签「{}」的颜色无效: {}",
+                    template.name, tag.name, tag.color
+                ));
+            }
+        }
+        if let Some(color) = template.preset.color.as_deref() {
+            if !is_valid_color(color) {
+                return Err(format!("模版「{}」的颜色无效: {color}", template.name));
+            }
+        }
+        for field in &template.preset.custom_fields {
+            validate_custom_field_value(&field.name, &field.kind, &field.value)?;
+        }
+    }
+    Ok(())
+}
+
+fn find_story_entry<'a>(
+    manifest: &'a ProjectManifest,
+    story_id: &str,
+) -> Option<&'a StoryManifestEntry> {
+    manifest
+        .stories
+        .iter()
+        .find(|item| item.story.id == story_id)
+}
+
+fn find_story_entry_mut<'a>(
+    manifest: &'a mut ProjectManifest,
+    story_id: &str,
+) -> Option<&'a mut StoryManifestEntry> {
+    manifest
+        .stories
+        .iter_mut()
+        .find(|item| item.story.id == story_id)
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+fn is_whole_word_match(haystack: &str, start: usize, end: usize) -> bool {
+    let needle = &haystack[start..end];
+    let before_ok = match (haystack[..start].chars().next_back(), needle.chars().next()) {
+        (Some(prev), Some(first)) => !(is_word_char(prev) && is_word_char(first)),
+        _ => true,
+    };
+    let after_ok = match (haystack[end..].chars().next(), needle.chars().next_back()) {
+        (Some(next), Some(last)) => !(is_word_char(next) && is_word_char(last)),
+        _ => true,
+    };
+    before_ok && after_ok
+}
+
+fn contains_whole_word(haystack: &str, needle: &str) -> bool {
+    if needle.is_empty() {
+        return false;
+    }
+    haystack
+        .match_indices(needle)
+        .any(|(start, matched)| is_whole_word_match(haystack, start, start + matched.len()))
+}
+
+fn is_id_key(key: &str) -> bool {
+    key == "id" || key.ends_with("Id")
+}
+
+fn collect_text_values<'a>(value: &'a serde_json::Value, out: &mut Vec<&'a str>) {
+    match value {
+        serde_json::Value::String(text) => out.push(text),
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_text_values(item, out);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (key, item) in map {
+                if !is_id_key(key) {
+                    collect_text_values(item, out);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn text_word_count(text: &str) -> usize {
+    // CJK characters count individually; other scripts count by whitespace-separated words.
+    let mut count = 0;
+    let mut in_word = false;
+    for ch in text.chars() {
+        let is_cjk = matches!(ch as u32, 0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF);
+        if is_cjk {
+            count += 1;
+            in_word = false;
+        } else if ch.is_alphanumeric() {
+            if !in_word {
+                count += 1;
+            }
+            in_word = true;
+        } else {
+            in_word = false;
+        }
+    }
+    count
+}
+
+fn collect_outline(nodes: &[serde_json::Value], depth: usize, out: &mut Vec<OutlineEntry>) {
+    for node in nodes {
+        let mut texts = Vec::new();
+        if let Some(map) = node.as_object() {
+            for (key, value) in map {
+                if !matches!(key.as_str(), "title" | "children" | "type" | "updatedAt")
+                    && !is_id_key(key)
+                {
+                    collect_text_values(value, &mut texts);
+                }
+            }
+        }
+        out.push(OutlineEntry {
+            id: node_id(node).unwrap_or_default().to_string(),
+            title: node_title(node).unwrap_or("（未命名）").to_string(),
+            node_type: node
+                .get("type")
+                .and_then(|value| value.as_str())
+                .map(str::to_string),
+            depth,
+            word_count: texts.iter().map(|text| text_word_count(text)).sum(),
+        });
+        collect_outline(tree_children(node), depth + 1, out);
+    }
+}
+
+fn node_id(node: &serde_json::Value) -> Option<&str> {
+    node.get("id").and_then(|value| value.as_str())
+}
+
+fn node_title(node: &serde_json::Value) -> Option<&str> {
+    node.get("title")
+        .and_then(|value| value.as_str())
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+}
+
+fn compute_setting_backlinks(settings: &[serde_json::Value]) -> HashMap<String, Vec<String>> {
+    let mut backlinks = HashMap::new();
+    for target in settings {
+        let Some(target_id) = node_id(target) else {
+            continue;
+        };
+        let mut sources = Vec::new();
+        if let Some(title) = node_title(target) {
+            for source in settings {
+                let Some(source_id) = node_id(source) else {
+                    continue;
+                };
+                if source_id == target_id {
+                    continue;
+                }
+                let mut texts = Vec::new();
+                collect_text_values(source, &mut texts);
+                if texts.iter().any(|text| contains_whole_word(text, title)) {
+                    sources.push(source_id.to_string());
+                }
+            }
+        }
+        backlinks.insert(target_id.to_string(), sources);
+    }
+    backlinks
+}
+
+fn find_text_matches(text: &str, find: &str, options: &FindReplaceOptions) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    if find.is_empty() {
+        return matches;
+    }
+    let needle = find.chars().collect::<Vec<_>>();
+    let mut search_from = 0;
+    for (start, _) in text.char_indices() {
+        if start < search_from {
+            continue;
+        }
+        let mut end = start;
+        let mut chars = text[start..].chars();
+        let matched = needle.iter().all(|expected| match chars.next() {
+            Some(actual) => {
+                end += actual.len_utf8();
+                if options.case_sensitive {
+                    actual == *expected
+                } else {
+                    actual.to_lowercase().eq(expected.to_lowercase())
+                }
+            }
+            None => false,
+        });
+        if matched && (!options.whole_word || is_whole_word_match(text, start, end)) {
+            matches.push((start, end));
+            search_from = end;
+        }
+    }
+    matches
+}
+
+fn is_replaceable_key(key: &str) -> bool {
+    !is_id_key(key)
+        && !matches!(
+            key,
+            "type"
+                | "color"
+                | "imageUrl"
+                | "createdAt"
+                | "updatedAt"
+                | "reasonType"
+                | "kind"
+                | "size"
+        )
+}
+
+fn replace_in_value(
+    value: &mut serde_json::Value,
+    path: &str,
+    find: &str,
+    replace: &str,
+    options: &FindReplaceOptions,
+    found: &mut Vec<(String, String)>,
+) -> usize {
+    match value {
+        serde_json::Value::String(text) => {
+            let matches = find_text_matches(text, find, options);
+            if matches.is_empty() {
+                return 0;
+            }
+            found.push((path.to_string(), text.clone()));
+            let mut next = String::with_capacity(text.len());
+            let mut cursor = 0;
+            for (start, end) in &matches {
+                next.push_str(&text[cursor..*start]);
+                next.push_str(replace);
+                cursor = *end;
+            }
+            next.push_str(&text[cursor..]);
+            *text = next;
+            matches.len()
+        }
+        serde_json::Value::Array(items) => items
+            .iter_mut()
+            .enumerate()
+            .map(|(index, item)| {
+                replace_in_value(
+                    item,
+                    &format!("{path}.{index}"),
+                    find,
+                    replace,
+                    options,
+                    found,
+                )
+            })
+            .sum(),
+        serde_json::Value::Object(map) => map
+            .iter_mut()
+            .filter(|(key, _)| is_replaceable_key(key))
+            .map(|(key, item)| {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                replace_in_value(item, &child_path, find, replace, options, found)
+            })
+            .sum(),
+        _ => 0,
+    }
+}
+
+fn replace_in_node(
+    node: &mut serde_json::Value,
+    find: &str,
+    replace: &str,
+    options: &FindReplaceOptions,
+    result: &mut ReplaceResult,
+    tree: bool,
+) {
+    let id = node_id(node).unwrap_or_default().to_string();
+    let mut found = Vec::new();
+    let mut count = 0;
+    if let serde_json::Value::Object(map) = node {
+        for (key, item) in map.iter_mut() {
+            if (tree && key == "children") || !is_replaceable_key(key) {
+                continue;
+            }
+            count += replace_in_value(item, key, find, replace, options, &mut found);
+        }
+        if tree {
+            if let Some(serde_json::Value::Array(children)) = map.get_mut("children") {
+                for child in children {
+                    replace_in_node(child, find, replace, options, result, tree);
+                }
+            }
+        }
+    }
+    if count > 0 {
+        result.replacements += count;
+        if tree {
+            result.tree_node_ids.push(id.clone());
+        } else {
+            result.setting_node_ids.push(id.clone());
+        }
+        result
+            .matches
+            .extend(found.into_iter().map(|(field, text)| ReplaceMatch {
+                node_id: id.clone(),
+                field,
+                text,
+            }));
+    }
+}
+
+fn sort_manifest_stories(stories: &mut [StoryManifestEntry]) {
+    stories.sort_by(|a, b| {
+        b.story
+            .pinned
+            .cmp(&a.story.pinned)
+            .then_with(|| match (a.order, b.order) {
+                (Some(left), Some(right)) => left.cmp(&right),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => compare_updated_desc(&a.story, &b.story),
+            })
+    });
+}
+
+fn compare_updated_desc(a: &Story, b: &Story) -> std::cmp::Ordering {
+    parse_timestamp(&b.updated_at)
+        .cmp(&parse_timestamp(&a.updated_at))
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+fn tree_children(node: &serde_json::Value) -> &[serde_json::Value] {
+    node.get("children")
+        .and_then(|value| value.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+fn find_tree_node<'a>(nodes: &'a [serde_json::Value], id: &str) -> Option<&'a serde_json::Value> {
+    for node in nodes {
+        if node_id(node) == Some(id) {
+            return Some(node);
+        }
+        if let Some(found) = find_tree_node(tree_children(node), id) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+fn find_tree_node_mut<'a>(
+    nodes: &'a mut [serde_json::Value],
+    id: &str,
+) -> Option<&'a mut serde_json::Value> {
+    for node in nodes {
+        if node_id(node) == Some(id) {
+            return Some(node);
+        }
+        if let Some(children) = node
+            .get_mut("children")
+            .and_then(|value| value.as_array_mut())
+        {
+            if let Some(found) = find_tree_node_mut(children, id) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+fn merge_node_patch(node: &mut serde_json::Value, patch: serde_json::Value) -> Result<(), String> {
+    let serde_json::Value::Object(fields) = patch else {
+        return Err("节点补丁必须是对象".to_string());
+    };
+    let Some(target) = node.as_object_mut() else {
+        return Err("节点数据无效".to_string());
+    };
+    for (key, value) in fields {
+        match key.as_str() {
+            "id" if value.as_str() == target.get("id").and_then(|id| id.as_str()) => {}
+            "id" | "children" => return Err(format!("不能通过补丁修改节点字段: {key}")),
+            _ if value.is_null() => {
+                target.remove(&key);
+            }
+            _ => {
+                target.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn detach_tree_node(nodes: &mut Vec<serde_json::Value>, id: &str) -> Option<serde_json::Value> {
+    if let Some(index) = nodes.iter().position(|node| node_id(node) == Some(id)) {
+        return Some(nodes.remove(index));
+    }
+    for node in nodes {
+        if let Some(children) = node
+            .get_mut("children")
+            .and_then(|value| value.as_array_mut())
+        {
+            if let Some(found) = detach_tree_node(children, id) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+fn write_story_settings(
+    state: &ProjectState,
+    root: &Path,
+    story_id: &str,
+    settings: Vec<serde_json::Value>,
+) -> Result<(), String> {
+    let story_lock = story_write_lock(state, story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Ok(mut pending) = state.pending_settings.lock() {
+        pending.remove(story_id);
+    }
+    write_story_settings_locked(state, root, story_id, settings)
+}
+
+// Dequeues under the story lock, so a debounced payload can never land after a newer
+// direct write to the same story.
+fn write_pending_settings(
+    state: &ProjectState,
+    story_id: &str,
+    due_only: bool,
+) -> Result<(), String> {
+    let story_lock = story_write_lock(state, story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let write = {
+        let Ok(mut pending) = state.pending_settings.lock() else {
+            return Ok(());
+        };
+        if due_only
+            && pending
+                .get(story_id)
+                .is_none_or(|write| write.due_at > Instant::now())
+        {
+            return Ok(());
+        }
+        pending.remove(story_id)
+    };
+    match write {
+        Some(write) => write_story_settings_locked(state, &write.root, story_id, write.settings),
+        None => Ok(()),
+    }
+}
+
+fn write_story_settings_locked(
+    state: &ProjectState,
+    root: &Path,
+    story_id: &str,
+    settings: Vec<serde_json::Value>,
+) -> Result<(), String> {
+    let _manifest_guard = manifest_write_lock(state);
+    let mut manifest = read_manifest(state, root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let _ = auto_snapshot_story(state, root, &entry.folder_name);
+    let current = read_workspace(&story_db_path(state, root, &entry.folder_name))?;
+    let next = Workspace {
+        settings,
+        tree: current.tree,
+        library: current.library,
+        repaired: false,
+        recovered: false,
+    };
+    write_workspace(&story_db_path(state, root, &entry.folder_name), &next)?;
+
+    entry.story.updated_at = now_rfc3339();
+    write_manifest(state, root, &manifest)?;
+    let _ = append_activity(root, story_id, "update_settings");
+    Ok(())
+}
+
+fn flush_pending_settings(state: &ProjectState) -> Result<(), String> {
+    let story_ids = match state.pending_settings.lock() {
+        Ok(guard) => guard.keys().cloned().collect::<Vec<_>>(),
+        Err(_) => return Ok(()),
+    };
+
+    let mut failures = Vec::new();
+    for story_id in story_ids {
+        if let Err(error) = write_pending_settings(state, &story_id, false) {
+            failures.push(format!("{story_id}: {error}"));
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("保存待写入设定失败: {}", failures.join("; ")))
+    }
+}
+
+fn next_due_settings(state: &ProjectState) -> Option<String> {
+    let mut pending = state.pending_settings.lock().ok()?;
+    loop {
+        let now = Instant::now();
+        let next = pending
+            .iter()
+            .min_by_key(|(_, write)| write.due_at)
+            .map(|(story_id, write)| (story_id.clone(), write.due_at));
+        pending = match next {
+            Some((story_id, due_at)) if due_at <= now => return Some(story_id),
+            Some((_, due_at)) => {
+                state
+                    .settings_wakeup
+                    .wait_timeout(pending, due_at - now)
+                    .ok()?
+                    .0
+            }
+            None => state.settings_wakeup.wait(pending).ok()?,
+        };
+    }
+}
+
+fn start_settings_worker(app: &AppHandle, state: &ProjectState) {
+    if state.settings_worker.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    let app = app.clone();
+    thread::spawn(move || {
+        let state = app.state::<ProjectState>();
+        while let Some(story_id) = next_due_settings(&state) {
+            if let Err(error) = write_pending_settings(&state, &story_id, true) {
+                let _ = app.emit(
+                    "settings-write-failed",
+                    SettingsWriteFailedEvent { story_id, error },
+                );
+            }
+        }
+        state.settings_worker.store(false, Ordering::Relaxed);
+    });
+}
+
+pub fn flush_pending_writes_on_exit(app: &AppHandle) {
+    if let Some(state) = app.try_state::<ProjectState>() {
+        if let Err(error) = flush_pending_settings(&state) {
+            log::error!("{error}");
+        }
+    }
+}
+
+fn trash_root(root: &Path) -> PathBuf {
+    root.join(".trash")
+}
+
+// A deleted story's folder only lives on in `.trash` for as long as its undo entry does.
+fn discard_undo_entries(entries: impl IntoIterator<Item = UndoEntry>) {
+    for entry in entries {
+        if let UndoEntry::DeleteStory { trash_path, .. } = entry {
+            let _ = fs::remove_dir_all(trash_path);
+        }
+    }
+}
+
+fn push_undo(state: &ProjectState, entry: UndoEntry) {
+    if let Ok(mut stack) = state.undo_stack.lock() {
+        stack.push(entry);
+        if stack.len() > UNDO_STACK_LIMIT {
+            let overflow = stack.len() - UNDO_STACK_LIMIT;
+            discard_undo_entries(stack.drain(..overflow));
+        }
+    }
+}
+
+fn clear_undo_stack(state: &ProjectState) {
+    if let Ok(mut stack) = state.undo_stack.lock() {
+        discard_undo_entries(stack.drain(..));
+    }
+}
+
+fn apply_undo(state: &ProjectState, root: &Path, entry: UndoEntry) -> Result<UndoResult, String> {
+    let story_lock = match &entry {
+        UndoEntry::DeleteStory { entry, .. } => Some(story_write_lock(state, &entry.story.id)),
+        UndoEntry::RenameStory { story_id, .. } | UndoEntry::StoryLibrary { story_id, .. } => {
+            Some(story_write_lock(state, story_id))
+        }
+        UndoEntry::GlobalLibrary { .. } => None,
+    };
+    let _story_guard = story_lock
+        .as_ref()
+        .map(|lock| lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    let _manifest_guard = manifest_write_lock(state);
+    let mut manifest = read_manifest(state, root)?;
+    match entry {
+        UndoEntry::DeleteStory {
+            index,
+            entry,
+            trash_path,
+        } => {
+            let story_id = entry.story.id.clone();
+            if find_story_entry(&manifest, &story_id).is_some() {
+                return Err("故事已存在，无法撤销删除".to_string());
+            }
+            let folder_path = story_root(state, root, &entry.folder_name);
+            if folder_path.exists() {
+                return Err("目标故事目录已存在，无法撤销删除".to_string());
+            }
+            if !trash_path.exists() {
+                return Err("回收站中的故事目录已丢失，无法撤销删除".to_string());
+            }
+
+            fs::rename(&trash_path, &folder_path)
+                .map_err(|error| format!("恢复故事目录失败: {error}"))?;
+            let index = index.min(manifest.stories.len());
+            manifest.stories.insert(index, entry);
+            if let Err(error) = write_manifest(state, root, &manifest) {
+                let _ = fs::rename(&folder_path, &trash_path);
+                return Err(error);
+            }
+            let _ = append_activity(root, &story_id, "undo_delete_story");
+            Ok(UndoResult {
+                action: "delete_story".to_string(),
+                story_id: Some(story_id),
+            })
+        }
+        UndoEntry::RenameStory {
+            story_id,
+            title,
+            folder_name,
+        } => {
+            let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+                return Err("故事不存在".to_string());
+            };
+
+            if entry.folder_name != folder_name {
+                let current_path = story_root(state, root, &entry.folder_name);
+                let previous_path = story_root(state, root, &folder_name);
+                if current_path.exists() {
+                    if previous_path.exists() {
+                        return Err("目标故事目录已存在，无法撤销重命名".to_string());
+                    }
+                    fs::rename(&current_path, &previous_path)
+                        .map_err(|error| format!("重命名故事目录失败: {error}"))?;
+                    migrate_story_asset_references(state, root, &entry.folder_name, &folder_name)?;
+                }
+                entry.folder_name = folder_name;
+            }
+            entry.story.title = title;
+            entry.story.updated_at = now_rfc3339();
+            write_manifest(state, root, &manifest)?;
+            let _ = append_activity(root, &story_id, "undo_rename_story");
+            Ok(UndoResult {
+                action: "rename_story".to_string(),
+                story_id: Some(story_id),
+            })
+        }
+        UndoEntry::StoryLibrary { story_id, library } => {
+            let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+                return Err("故事不存在".to_string());
+            };
+
+            let db_path = story_db_path(state, root, &entry.folder_name);
+            let mut current = read_workspace(&db_path)?;
+            current.library = library;
+            write_workspace(&db_path, &current)?;
+            entry.story.updated_at = now_rfc3339();
+            write_manifest(state, root, &manifest)?;
+            Ok(UndoResult {
+                action: "update_story_library".to_string(),
+                story_id: Some(story_id),
+            })
+        }
+        UndoEntry::GlobalLibrary { library } => {
+            manifest.shared_library = library;
+            write_manifest(state, root, &manifest)?;
+            Ok(UndoResult {
+                action: "update_global_library".to_string(),
+                story_id: None,
+            })
+        }
+    }
+}
+
+// The caller holds the story lock for `update.story_id` and the manifest lock.
+fn apply_workspace_update(
+    state: &ProjectState,
+    root: &Path,
+    manifest: &mut ProjectManifest,
+    update: WorkspaceUpdate,
+) -> Result<(), String> {
+    if let Some(settings) = &update.settings {
+        validate_settings(settings)?;
+    }
+    if let Some(library) = &update.library {
+        validate_library(library)?;
+        validate_template_types(state, root, library)?;
+    }
+    let Some(entry) = find_story_entry_mut(manifest, &update.story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    if update.settings.is_some() {
+        if let Ok(mut pending) = state.pending_settings.lock() {
+            pending.remove(&update.story_id);
+        }
+    }
+    let db_path = story_db_path(state, root, &entry.folder_name);
+    let mut current = read_workspace(&db_path)?;
+    if let Some(settings) = update.settings {
+        current.settings = settings;
+    }
+    if let Some(tree) = update.tree {
+        current.tree = tree;
+    }
+    if let Some(library) = update.library {
+        current.library = library;
+    }
+    write_workspace(&db_path, &current)?;
+    entry.story.updated_at = now_rfc3339();
+    Ok(())
+}
+
+fn resolve_backup_parent(
+    state: &ProjectState,
+    root: &Path,
+    backup_dir: Option<&str>,
+) -> Result<PathBuf, String> {
+    let configured = project_config(state, root)?.backup_dir;
+    let target = backup_dir
+        .or(configured.as_deref())
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| exports_root(state, root));
+    fs::create_dir_all(&target).map_err(|error| format!("创建备份目录失败: {error}"))?;
+    let probe = target.join(format!(".write-test-{}", Uuid::new_v4()));
+    fs::write(&probe, b"").map_err(|error| format!("备份目录不可写: {error}"))?;
+    let _ = fs::remove_file(&probe);
+    Ok(target)
+}
+
+// Millisecond names plus a counter keep two backups taken within the same instant apart.
+fn new_backup_dir(backup_parent: &Path) -> PathBuf {
+    let base = format!("backup-{}", Utc::now().format("%Y%m%d-%H%M%S%3f"));
+    let mut candidate = backup_parent.join(&base);
+    let mut suffix = 2;
+    while candidate.exists() || staging_path(&candidate).exists() {
+        candidate = backup_parent.join(format!("{base}-{suffix}"));
+        suffix += 1;
+    }
+    candidate
+}
+
+fn backup_exclusions(state: &ProjectState, root: &Path, backup_parent: &Path) -> Vec<String> {
+    let inside_root = match (fs::canonicalize(backup_parent), fs::canonicalize(root)) {
+        (Ok(target), Ok(root)) => target.starts_with(root),
+        _ => true,
+    };
+    if inside_root {
+        backup_excluded_paths(state, root)
+    } else {
+        BACKUP_EXCLUDED_PATHS
+            .iter()
+            .map(|item| item.to_string())
+            .collect()
+    }
+}
+
+fn create_backup(
+    state: &ProjectState,
+    root: &Path,
+    backup_parent: Option<&str>,
+) -> Result<PathBuf, String> {
+    let export_dir = resolve_backup_parent(state, root, backup_parent)?;
+    ensure_free_space(&export_dir, compute_project_size(state, root)?.total_bytes)?;
+    let backup_dir = new_backup_dir(&export_dir);
+    let staging_dir = staging_path(&backup_dir);
+    let excluded = backup_exclusions(state, root, &export_dir);
+    if let Err(error) = copy_dir_recursive(
+        root,
+        &staging_dir,
+        &excluded.iter().map(String::as_str).collect::<Vec<_>>(),
+    ) {
+        let _ = fs::remove_dir_all(&staging_dir);
+        return Err(error);
+    }
+    fs::rename(&staging_dir, &backup_dir).map_err(|error| {
+        let _ = fs::remove_dir_all(&staging_dir);
+        format!("完成备份失败: {error}")
+    })?;
+    prune_backups(state, root, &export_dir)?;
+    Ok(backup_dir)
+}
+
+fn prune_backups(state: &ProjectState, root: &Path, backup_parent: &Path) -> Result<(), String> {
+    let retention = project_config(state, root)?.backup_retention;
+    let Ok(entries) = fs::read_dir(backup_parent) else {
+        return Ok(());
+    };
+    let mut backups = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy())
+                .is_some_and(|name| name.starts_with("backup-") && !name.ends_with(".tmp"))
+        })
+        .collect::<Vec<_>>();
+    backups.sort();
+    backups.reverse();
+    for path in backups.into_iter().skip(retention.max(1)) {
+        let _ = fs::remove_dir_all(path);
+    }
+    Ok(())
+}
+
+fn start_auto_backup(app: &AppHandle, state: &ProjectState, root: &Path) {
+    let interval_minutes = project_config(state, root)
+        .map(|config| config.auto_backup_interval_minutes)
+        .unwrap_or(0);
+    let Ok(mut guard) = state.auto_backup.lock() else {
+        return;
+    };
+    if guard
+        .as_ref()
+        .is_some_and(|timer| timer.root == root && timer.interval_minutes == interval_minutes)
+    {
+        return;
+    }
+    if let Some(timer) = guard.take() {
+        timer.stop.store(true, Ordering::Relaxed);
+    }
+    if interval_minutes == 0 {
+        return;
+    }
+
+    let interval = chrono::Duration::minutes(interval_minutes as i64);
+    let stop = Arc::new(AtomicBool::new(false));
+    let status = Arc::new(Mutex::new(AutoBackupStatus {
+        enabled: true,
+        interval_minutes,
+        next_backup_at: Some(
+            (Utc::now() + interval).to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        ),
+        ..AutoBackupStatus::default()
+    }));
+    *guard = Some(AutoBackupTimer {
+        root: root.to_path_buf(),
+        interval_minutes,
+        stop: stop.clone(),
+        status: status.clone(),
+    });
+
+    let app = app.clone();
+    let root = root.to_path_buf();
+    thread::spawn(move || {
+        let mut next = Utc::now() + interval;
+        while !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_secs(1));
+            if Utc::now() < next || stop.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            let result = create_backup(&app.state::<ProjectState>(), &root, None);
+            next = Utc::now() + interval;
+            if let Ok(mut status) = status.lock() {
+                match result {
+                    Ok(_) => {
+                        status.last_backup_at = Some(now_rfc3339());
+                        status.last_error = None;
+                    }
+                    Err(error) => status.last_error = Some(error),
+                }
+                status.next_backup_at =
+                    Some(next.to_rfc3339_opts(chrono::SecondsFormat::Millis, true));
+            }
+        }
+    });
+}
+
+fn stop_auto_backup(state: &ProjectState) {
+    if let Ok(mut guard) = state.auto_backup.lock() {
+        if let Some(timer) = guard.take() {
+            timer.stop.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+fn fuzzy_score(query: &[char], text: &str) -> Option<i64> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    if query.is_empty() || text.is_empty() {
+        return None;
+    }
+
+    if let Some(position) = text.windows(query.len()).position(|window| window == query) {
+        let prefix_bonus = if position == 0 { 50 } else { 0 };
+        return Some(1000 + prefix_bonus - position as i64 - text.len() as i64);
+    }
+
+    let mut score = 0;
+    let mut last_match: Option<usize> = None;
+    let mut chars = text.iter().enumerate();
+    for wanted in query {
+        let (index, _) = chars.find(|(_, ch)| *ch == wanted)?;
+        score += match last_match {
+            Some(last) if index == last + 1 => 10,
+            _ => 1,
+        };
+        last_match = Some(index);
+    }
+    Some(score * 10 - text.len() as i64)
+}
+
+fn migrate_legacy_story_folders(
+    state: &ProjectState,
+    root: &Path,
+    manifest: &mut ProjectManifest,
+) -> Result<MigrationReport, String> {
+    let mut report = MigrationReport::default();
+    let Ok(entries) = fs::read_dir(stories_root(state, root)) else {
+        return Ok(report);
+    };
+    let mut legacy_names = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| Uuid::parse_str(name).is_ok())
+        .collect::<Vec<_>>();
+    legacy_names.sort();
+
+    for legacy_name in legacy_names {
+        let legacy_dir = stories_root(state, root).join(&legacy_name);
+        let Some(index) = manifest
+            .stories
+            .iter()
+            .position(|entry| entry.story.id.eq_ignore_ascii_case(&legacy_name))
+        else {
+            report.unmatched.push(legacy_name);
+            continue;
+        };
+
+        if manifest.stories[index].folder_name == legacy_name {
+            let entry = &manifest.stories[index];
+            let folder_name =
+                unique_story_folder_name(manifest, &entry.story.title, &entry.story.id);
+            manifest.stories[index].folder_name = folder_name;
+        }
+        let entry = &manifest.stories[index];
+        let target_dir = stories_root(state, root).join(&entry.folder_name);
+        if !target_dir.exists() {
+            fs::rename(&legacy_dir, &target_dir)
+                .map_err(|error| format!("迁移故事目录失败: {error}"))?;
+        } else if !target_dir.join("story.db").exists() && legacy_dir.join("story.db").exists() {
+            fs::rename(legacy_dir.join("story.db"), target_dir.join("story.db"))
+                .map_err(|error| format!("迁移故事数据库失败: {error}"))?;
+            let _ = fs::remove_dir(&legacy_dir);
+        } else {
+            report.conflicts.push(legacy_name);
+            continue;
+        }
+        report.moved.push(MigratedFolder {
+            story_id: entry.story.id.clone(),
+            from: legacy_name,
+            to: entry.folder_name.clone(),
+        });
+    }
+
+    Ok(report)
+}
+
+fn repair_folder_name_collisions(
+    state: &ProjectState,
+    root: &Path,
+    manifest: &mut ProjectManifest,
+) -> Result<Vec<FolderRepair>, String> {
+    let mut repairs = Vec::new();
+    let mut seen = HashSet::new();
+    for index in 0..manifest.stories.len() {
+        let old_folder_name = manifest.stories[index].folder_name.clone();
+        if seen.insert(old_folder_name.clone()) {
+            continue;
+        }
+
+        let entry = &manifest.stories[index];
+        let story_id = entry.story.id.clone();
+        let mut new_folder_name = unique_story_folder_name(manifest, &entry.story.title, &story_id);
+        let mut suffix = 2;
+        while seen.contains(&new_folder_name)
+            || stories_root(state, root).join(&new_folder_name).exists()
+        {
+            new_folder_name = format!(
+                "{}-{suffix}",
+                unique_story_folder_name(manifest, &entry.story.title, &story_id)
+            );
+            suffix += 1;
+        }
+
+        let shared_dir = stories_root(state, root).join(&old_folder_name);
+        if shared_dir.is_dir() {
+            copy_dir_recursive(
+                &shared_dir,
+                &stories_root(state, root).join(&new_folder_name),
+                &["history"],
+            )?;
+        }
+        seen.insert(new_folder_name.clone());
+        manifest.stories[index].folder_name = new_folder_name.clone();
+        repairs.push(FolderRepair {
+            story_id,
+            old_folder_name,
+            new_folder_name,
+        });
+    }
+    Ok(repairs)
+}
+
+fn load_project_data(state: &ProjectState, root: &Path) -> Result<ProjectData, String> {
+    project_data_from_manifest(state, root, read_manifest(state, root)?)
+}
+
+// Reads project.json exactly once for activation: the warning comes from the same parse that
+// seeds the manifest cache and builds the project data.
+fn load_project_data_with_warning(
+    state: &ProjectState,
+    root: &Path,
+) -> Result<(ProjectData, Option<ManifestWarning>), String> {
+    let (manifest, warning) = read_manifest_with_warning(root)?;
+    cache_manifest(state, root, &manifest);
+    Ok((project_data_from_manifest(state, root, manifest)?, warning))
+}
+
+fn project_data_from_manifest(
+    state: &ProjectState,
+    root: &Path,
+    mut manifest: ProjectManifest,
+) -> Result<ProjectData, String> {
+    let mut workspaces = std::collections::HashMap::new();
+    for entry in &mut manifest.stories {
+        let db_path = story_db_path(state, root, &entry.folder_name);
+        let legacy_db_path = stories_root(state, root)
+            .join(&entry.story.id)
+            .join("story.db");
+
+        if !db_path.exists() && legacy_db_path.exists() && !is_read_only(state) {
+            if let Some(parent) = db_path.parent() {
+                fs::create_dir_all(parent).map_err(|error| format!("迁移故事目录失败: {error}"))?;
+            }
+            fs::rename(&legacy_db_path, &db_path)
+                .map_err(|error| format!("迁移故事数据库失败: {error}"))?;
+        }
+
+        if let Some(touched_at) = read_workspace_touched_at(&db_path, is_read_only(state)) {
+            if parse_timestamp(&touched_at) > parse_timestamp(&entry.story.updated_at) {
+                entry.story.updated_at = touched_at;
+            }
+        }
+
+        let workspace = read_story_workspace(state, &db_path)?;
+        workspaces.insert(entry.story.id.clone(), workspace);
+    }
+    sort_manifest_stories(&mut manifest.stories);
+
+    Ok(ProjectData {
+        stories: manifest
+            .stories
+            .into_iter()
+            .map(|item| item.story)
+            .collect(),
+        workspaces,
+        shared_library: manifest.shared_library,
+    })
+}
+
+fn resolve_state_root(app: &AppHandle, state: &ProjectState) -> Result<Option<PathBuf>, String> {
+    if let Ok(guard) = state.project_root.lock() {
+        if let Some(path) = guard.as_ref() {
+            return Ok(Some(path.clone()));
+        }
+    }
+
+    read_selected_root(app)
+}
+
+fn activate_root(
+    app: &AppHandle,
+    state: &ProjectState,
+    root: &Path,
+    read_only: bool,
+) -> Result<(), String> {
+    let config = read_config(root)?;
+    cache_config(state, root, &config);
+    if let Ok(mut guard) = state.project_root.lock() {
+        if guard.as_deref() != Some(root) {
+            clear_undo_stack(state);
+            clear_manifest_cache(state);
+            if !read_only {
+                let _ = fs::remove_dir_all(trash_root(root));
+            }
+        }
+        *guard = Some(root.to_path_buf());
+    }
+    state.read_only.store(read_only, Ordering::Relaxed);
+    if read_only {
+        stop_lock_heartbeat(state);
+        stop_auto_backup(state);
+    } else {
+        start_lock_heartbeat(state, root)?;
+    }
+    record_manifest_mtime(state, root);
+    start_project_watcher(app, state, root)?;
+    if read_only {
+        return Ok(());
+    }
+    start_auto_backup(app, state, root);
+    write_selected_root(app, root)
+}
+
+fn set_active_root(app: &AppHandle, state: &ProjectState, root: &Path) -> Result<(), String> {
+    activate_root(app, state, root, false)
+}
+
+fn open_root(app: &AppHandle, state: &ProjectState, target: &Path) -> Result<(), String> {
+    if !target.exists() {
+        return Err("项目目录不存在".to_string());
+    }
+
+    if !project_manifest_path(target).exists() {
+        return Err("未找到 project.json，请先创建项目目录或选择有效项目目录".to_string());
+    }
+
+    ensure_root_writable(target)?;
+    ensure_root_layout(state, target)?;
+    let _ = read_manifest(state, target)?;
+    set_active_root(app, state, target)
+}
+
+pub fn handle_launch_args(app: &AppHandle, args: &[String]) {
+    let paths = args
+        .iter()
+        .filter(|arg| !arg.starts_with('-'))
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    let state = app.state::<ProjectState>();
+
+    if let Some(target) = paths.iter().find(|path| path.is_dir()) {
+        if let Err(error) = open_root(app, &state, target) {
+            log::warn!("无法打开命令行指定的项目 {}: {error}", target.display());
+        }
+    }
+
+    let export_file = paths.iter().find(|path| {
+        path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("json")
+    });
+    if let Some(path) = export_file {
+        match preview_export_file(path) {
+            Ok(preview) => {
+                if let Ok(mut pending) = state.pending_import.lock() {
+                    *pending = Some(preview.clone());
+                }
+                let _ = app.emit("import-requested", preview);
+            }
+            Err(error) => log::warn!("无法读取导出文件 {}: {error}", path.display()),
+        }
+    }
+}
+
+fn require_active_root(app: &AppHandle, state: &ProjectState) -> Result<PathBuf, String> {
+    resolve_state_root(app, state)?.ok_or_else(|| "请先创建项目目录或打开已有项目".to_string())
+}
+
+fn is_read_only(state: &ProjectState) -> bool {
+    state.read_only.load(Ordering::Relaxed)
+}
+
+// Every mutating command enters through here, so an external edit of project.json is
+// rejected before anything on disk is touched; `overwrite_external_manifest_changes`
+// is the one way to proceed anyway.
+fn require_writable_root(app: &AppHandle, state: &ProjectState) -> Result<PathBuf, String> {
+    if is_read_only(state) {
+        return Err(READ_ONLY_PROJECT.to_string());
+    }
+    let root = require_active_root(app, state)?;
+    ensure_manifest_unchanged(state, &root)?;
+    Ok(root)
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn open_path_in_file_manager(path: &Path) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    let mut cmd = {
+        let mut c = Command::new("open");
+        c.arg(path);
+        c
+    };
+
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        let mut c = Command::new("explorer");
+        c.arg(path);
+        c
+    };
+
+    cmd.status()
+        .map_err(|error| format!("打开路径失败: {error}"))
+        .and_then(|status| {
+            if status.success() {
+                Ok(())
+            } else {
+                Err("打开路径失败".to_string())
+            }
+        })
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn open_path_in_file_manager(path: &Path) -> Result<(), String> {
+    let launchers: [(&str, &[&str]); 2] = [("xdg-open", &[]), ("gio", &["open"])];
+    let file_managers = ["nautilus", "dolphin"];
+
+    let mut failures = Vec::new();
+    for (program, args) in launchers {
+        match Command::new(program).args(args).arg(path).output() {
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                // Some desktops report a nonzero exit even though the file manager opened.
+                if output.status.success() || stderr.is_empty() {
+                    return Ok(());
+                }
+                failures.push(format!("{program}: {stderr}"));
+            }
+            Err(error) => failures.push(format!("{program}: {error}")),
+        }
+    }
+    for program in file_managers {
+        match Command::new(program).arg(path).spawn() {
+            Ok(_) => return Ok(()),
+            Err(error) => failures.push(format!("{program}: {error}")),
+        }
+    }
+
+    Err(format!("打开路径失败: {}", failures.join("; ")))
+}
+
+fn copy_dir_recursive(from: &Path, to: &Path, excluded: &[&str]) -> Result<(), String> {
+    copy_dir_recursive_inner(from, to, excluded, &mut HashSet::new())
+}
+
+fn copy_dir_recursive_inner(
+    from: &Path,
+    to: &Path,
+    excluded: &[&str],
+    visited: &mut HashSet<PathBuf>,
+) -> Result<(), String> {
+    let canonical = fs::canonicalize(from).map_err(|error| format!("读取目录失败: {error}"))?;
+    if !visited.insert(canonical) {
+        log::warn!("跳过重复访问的目录: {}", from.display());
+        return Ok(());
+    }
+
+    fs::create_dir_all(to).map_err(|error| format!("创建备份目录失败: {error}"))?;
+    for entry in fs::read_dir(from).map_err(|error| format!("读取目录失败: {error}"))? {
+        let entry = entry.map_err(|error| format!("读取目录失败: {error}"))?;
+        if excluded.contains(&entry.file_name().to_string_lossy().as_ref()) {
+            continue;
+        }
+        let src = entry.path();
+        let dst = to.join(entry.file_name());
+        let meta =
+            fs::symlink_metadata(&src).map_err(|error| format!("读取文件信息失败: {error}"))?;
+        if meta.file_type().is_symlink() {
+            log::warn!("跳过符号链接: {}", src.display());
+        } else if meta.is_dir() {
+            copy_dir_recursive_inner(&src, &dst, &[], visited)?;
+        } else {
+            fs::copy(&src, &dst).map_err(|error| format!("复制文件失败: {error}"))?;
+        }
+    }
+    Ok(())
+}
+
+fn bytes_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn file_sha256(path: &Path) -> Option<String> {
+    fs::read(path).ok().map(|raw| bytes_sha256(&raw))
+}
+
+fn project_copy_units(
+    state: &ProjectState,
+    root: &Path,
+    target: &Path,
+    excluded: &[&str],
+) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let stories = stories_root(state, root);
+    let mut units = Vec::new();
+    for entry in fs::read_dir(root).map_err(|error| format!("读取目录失败: {error}"))? {
+        let entry = entry.map_err(|error| format!("读取目录失败: {error}"))?;
+        if excluded.contains(&entry.file_name().to_string_lossy().as_ref()) {
+            continue;
+        }
+        let src = entry.path();
+        let dst = target.join(entry.file_name());
+        if src == stories && src.is_dir() {
+            for story in fs::read_dir(&src).map_err(|error| format!("读取目录失败: {error}"))?
+            {
+                let story = story.map_err(|error| format!("读取目录失败: {error}"))?;
+                units.push((story.path(), dst.join(story.file_name())));
+            }
+            fs::create_dir_all(&dst).map_err(|error| format!("创建目标目录失败: {error}"))?;
+        } else {
+            units.push((src, dst));
+        }
+    }
+    Ok(units)
+}
+
+fn copy_project_tree(
+    app: &AppHandle,
+    state: &ProjectState,
+    root: &Path,
+    target: &Path,
+    excluded: &[&str],
+) -> Result<(), String> {
+    fs::create_dir_all(target).map_err(|error| format!("创建目标目录失败: {error}"))?;
+    let units = project_copy_units(state, root, target, excluded)?;
+    let total_bytes = dir_size(root, excluded);
+    let mut copied_bytes = 0;
+    for (src, dst) in units {
+        let meta =
+            fs::symlink_metadata(&src).map_err(|error| format!("读取文件信息失败: {error}"))?;
+        if meta.file_type().is_symlink() {
+            log::warn!("跳过符号链接: {}", src.display());
+            continue;
+        }
+        if meta.is_dir() {
+            copy_dir_recursive(&src, &dst, &[])?;
+            copied_bytes += dir_size(&src, &[]);
+        } else {
+            fs::copy(&src, &dst).map_err(|error| format!("复制文件失败: {error}"))?;
+            copied_bytes += meta.len();
+        }
+        let _ = app.emit(
+            "project-move-progress",
+            ProjectMoveProgressEvent {
+                copied_bytes,
+                total_bytes,
+                current: src.to_string_lossy().to_string(),
+            },
+        );
+    }
+    Ok(())
+}
+
+fn verify_project_copy(state: &ProjectState, root: &Path, target: &Path) -> Result<(), String> {
+    let manifest = read_manifest(state, root)?;
+    let mut files = vec![project_manifest_path(root)];
+    files.extend(
+        manifest
+            .stories
+            .iter()
+            .map(|entry| story_db_path(state, root, &entry.folder_name))
+            .filter(|path| path.exists()),
+    );
+
+    for source in files {
+        let relative = source
+            .strip_prefix(root)
+            .map_err(|error| error.to_string())?;
+        let copied = target.join(relative);
+        let expected = file_sha256(&source);
+        if expected.is_none() || expected != file_sha256(&copied) {
+            return Err(format!("校验复制结果失败: {}", relative.display()));
+        }
+    }
+    Ok(())
+}
+
+fn backup_state_path(backup_parent: &Path) -> PathBuf {
+    backup_parent.join(".backup-manifest.json")
+}
+
+fn collect_backup_files(
+    root: &Path,
+    dir: &Path,
+    excluded: &[&str],
+    files: &mut Vec<(String, BackupFileState)>,
+) -> Result<(), String> {
+    for entry in fs::read_dir(dir).map_err(|error| format!("读取目录失败: {error}"))? {
+        let entry = entry.map_err(|error| format!("读取目录失败: {error}"))?;
+        if dir == root && excluded.contains(&entry.file_name().to_string_lossy().as_ref()) {
+            continue;
+        }
+        let path = entry.path();
+        let meta =
+            fs::symlink_metadata(&path).map_err(|error| format!("读取文件信息失败: {error}"))?;
+        if meta.file_type().is_symlink() {
+            log::warn!("跳过符号链接: {}", path.display());
+        } else if meta.is_dir() {
+            collect_backup_files(root, &path, excluded, files)?;
+        } else {
+            let relative = path
+                .strip_prefix(root)
+                .map_err(|error| error.to_string())?
+                .to_string_lossy()
+                .replace('\\', "/");
+            let modified_ms = meta
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_millis() as u64)
+                .unwrap_or(0);
+            files.push((
+                relative,
+                BackupFileState {
+                    size: meta.len(),
+                    modified_ms,
+                },
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn copy_incremental_files(
+    root: &Path,
+    staging_dir: &Path,
+    files: &[(String, BackupFileState)],
+    previous: Option<&BackupState>,
+    report: &mut BackupReport,
+) -> Result<(), String> {
+    for (relative, file_state) in files {
+        let target = staging_dir.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|error| format!("创建备份目录失败: {error}"))?;
+        }
+
+        let unchanged = previous.and_then(|state| {
+            (state.files.get(relative) == Some(file_state))
+                .then(|| Path::new(&state.backup_dir).join(relative))
+                .filter(|path| path.is_file())
+        });
+        match unchanged {
+            Some(prior) if fs::hard_link(&prior, &target).is_ok() => {
+                report.skipped.push(relative.clone());
+            }
+            _ => {
+                fs::copy(root.join(relative), &target)
+                    .map_err(|error| format!("复制文件失败: {error}"))?;
+                report.copied.push(relative.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn incremental_backup(
+    state: &ProjectState,
+    root: &Path,
+    backup_dir: &Path,
+) -> Result<BackupReport, String> {
+    let backup_parent = backup_dir
+        .parent()
+        .ok_or_else(|| "无效的备份目录".to_string())?;
+    let previous = fs::read_to_string(backup_state_path(backup_parent))
+        .ok()
+        .and_then(|raw| serde_json::from_str::<BackupState>(&raw).ok())
+        .filter(|state| Path::new(&state.backup_dir).is_dir());
+    let excluded = backup_exclusions(state, root, backup_parent);
+    let excluded = excluded.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut files = Vec::new();
+    collect_backup_files(root, root, &excluded, &mut files)?;
+
+    let staging_dir = staging_path(backup_dir);
+    let mut report = BackupReport {
+        backup_path: backup_dir.to_string_lossy().to_string(),
+        copied: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let result = copy_incremental_files(root, &staging_dir, &files, previous.as_ref(), &mut report)
+        .and_then(|_| {
+            fs::create_dir_all(&staging_dir)
+                .map_err(|error| format!("创建备份目录失败: {error}"))?;
+            fs::rename(&staging_dir, backup_dir).map_err(|error| format!("完成备份失败: {error}"))
+        });
+    if let Err(error) = result {
+        let _ = fs::remove_dir_all(&staging_dir);
+        return Err(error);
+    }
+
+    let backup_state = BackupState {
+        backup_dir: backup_dir.to_string_lossy().to_string(),
+        files: files.into_iter().collect(),
+    };
+    let raw = serde_json::to_vec_pretty(&backup_state).map_err(|error| error.to_string())?;
+    fs::write(backup_state_path(backup_parent), raw)
+        .map_err(|error| format!("写入备份记录失败: {error}"))?;
+    Ok(report)
+}
+
+fn derive_export_key(password: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|error| format!("生成加密密钥失败: {error}"))?;
+    Ok(key)
+}
+
+fn encrypt_export(password: &str, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let mut salt = [0u8; ENCRYPTED_EXPORT_SALT_LEN];
+    let mut nonce = [0u8; ENCRYPTED_EXPORT_NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_export_key(password, &salt)?;
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|error| error.to_string())?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| "加密导出数据失败".to_string())?;
+
+    let mut raw = Vec::with_capacity(
+        ENCRYPTED_EXPORT_MAGIC.len() + salt.len() + nonce.len() + ciphertext.len(),
+    );
+    raw.extend_from_slice(ENCRYPTED_EXPORT_MAGIC);
+    raw.extend_from_slice(&salt);
+    raw.extend_from_slice(&nonce);
+    raw.extend_from_slice(&ciphertext);
+    Ok(raw)
+}
+
+fn decrypt_export(password: &str, raw: &[u8]) -> Result<Vec<u8>, String> {
+    let header_len =
+        ENCRYPTED_EXPORT_MAGIC.len() + ENCRYPTED_EXPORT_SALT_LEN + ENCRYPTED_EXPORT_NONCE_LEN;
+    if raw.len() <= header_len || !raw.starts_with(ENCRYPTED_EXPORT_MAGIC) {
+        return Err("无效的加密导出文件".to_string());
+    }
+    let (salt, rest) = raw[ENCRYPTED_EXPORT_MAGIC.len()..].split_at(ENCRYPTED_EXPORT_SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(ENCRYPTED_EXPORT_NONCE_LEN);
+
+    let key = derive_export_key(password, salt)?;
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|error| error.to_string())?;
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "密码错误或加密文件已损坏".to_string())
+}
+
+fn content_hash<T: Serialize>(value: &T) -> String {
+    let raw = serde_json::to_vec(value).unwrap_or_default();
+    Sha256::digest(raw)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn preview_export_file(path: &Path) -> Result<ExportPreview, String> {
+    let raw = fs::read(path).map_err(|error| format!("读取导出文件失败: {error}"))?;
+    let value = serde_json::from_slice::<serde_json::Value>(&raw)
+        .map_err(|error| format!("解析导出文件失败: {error}"))?;
+    if value.get("app").and_then(|app| app.as_str()) != Some("takecopter") {
+        return Err("无效的导出文件来源".to_string());
+    }
+
+    let path = path.to_string_lossy().to_string();
+    if value.get("data").is_some() {
+        let payload = serde_json::from_value::<ExportedProjectData>(value)
+            .map_err(|error| format!("解析项目文件失败: {error}"))?;
+        Ok(ExportPreview {
+            path,
+            kind: "project".to_string(),
+            schema_version: payload.schema_version,
+            exported_at: payload.exported_at,
+            story_titles: payload
+                .data
+                .stories
+                .into_iter()
+                .map(|story| story.title)
+                .collect(),
+        })
+    } else if value.get("story").is_some() {
+        let payload = serde_json::from_value::<ExportedStoryData>(value)
+            .map_err(|error| format!("解析故事文件失败: {error}"))?;
+        Ok(ExportPreview {
+            path,
+            kind: "story".to_string(),
+            schema_version: payload.schema_version,
+            exported_at: payload.exported_at,
+            story_titles: vec![payload.story.title],
+        })
+    } else {
+        Err("无法识别的导出文件".to_string())
+    }
+}
+
+fn read_project_export_file(path: &str) -> Result<ExportedProjectData, String> {
+    let raw =
+        fs::read_to_string(path.trim()).map_err(|error| format!("读取项目文件失败: {error}"))?;
+    let payload = serde_json::from_str::<ExportedProjectData>(&raw)
+        .map_err(|error| format!("解析项目文件失败: {error}"))?;
+    if payload.app != "takecopter" {
+        return Err("无效的项目文件来源".to_string());
+    }
+    Ok(payload)
+}
+
+fn diff_project_data(before: &ProjectData, after: &ProjectData) -> ProjectDiff {
+    let mut diff = ProjectDiff {
+        shared_library_changed: content_hash(&before.shared_library)
+            != content_hash(&after.shared_library),
+        ..ProjectDiff::default()
+    };
+
+    for story in &after.stories {
+        let Some(previous) = before.stories.iter().find(|item| item.id == story.id) else {
+            diff.added.push(story.clone());
+            continue;
+        };
+
+        let previous_workspace = before.workspaces.get(&story.id);
+        let next_workspace = after.workspaces.get(&story.id);
+        let changed = |pick: fn(&Workspace) -> String| {
+            previous_workspace.map(pick) != next_workspace.map(pick)
+        };
+        let settings_changed = changed(|workspace| content_hash(&workspace.settings));
+        let tree_changed = changed(|workspace| content_hash(&workspace.tree));
+        let library_changed = changed(|workspace| content_hash(&workspace.library));
+        let metadata_changed = content_hash(previous) != content_hash(story);
+
+        if settings_changed || tree_changed || library_changed || metadata_changed {
+            diff.modified.push(StoryDiff {
+                story_id: story.id.clone(),
+                title: story.title.clone(),
+                updated_at_before: previous.updated_at.clone(),
+                updated_at_after: story.updated_at.clone(),
+                metadata_changed,
+                settings_changed,
+                tree_changed,
+                library_changed,
+            });
+        } else {
+            diff.unchanged_count += 1;
+        }
+    }
+
+    diff.removed = before
+        .stories
+        .iter()
+        .filter(|story| !after.stories.iter().any(|item| item.id == story.id))
+        .cloned()
+        .collect();
+    diff
+}
+
+fn merge_library(target: &mut SettingLibrary, incoming: &SettingLibrary) -> bool {
+    let mut changed = false;
+    for tag in &incoming.tags {
+        let key = tag.name.trim().to_lowercase();
+        if !target
+            .tags
+            .iter()
+            .any(|item| item.name.trim().to_lowercase() == key)
+        {
+            target.tags.push(tag.clone());
+            changed = true;
+        }
+    }
+    for category in &incoming.categories {
+        let key = category_key(category);
+        if !key.is_empty()
+            && !target
+                .categories
+                .iter()
+                .any(|item| category_key(item) == key)
+        {
+            target.categories.push(category.trim().to_string());
+            changed = true;
+        }
+    }
+    for template in &incoming.templates {
+        if !target.templates.iter().any(|item| item.id == template.id) {
+            target.templates.push(template.clone());
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn collect_node_tag_names(node: &serde_json::Value, out: &mut HashSet<String>) {
+    if let Some(tags) = node.get("tags").and_then(|value| value.as_array()) {
+        for tag in tags {
+            if let Some(name) = tag.get("name").and_then(|value| value.as_str()) {
+                out.insert(name.trim().to_lowercase());
+            }
+        }
+    }
+}
+
+fn prune_library_to_workspaces<'a>(
+    library: &mut SettingLibrary,
+    workspaces: impl Iterator<Item = &'a Workspace>,
+) {
+    let mut used_tags = HashSet::new();
+    let mut used_categories = HashSet::new();
+    for workspace in workspaces {
+        for node in &workspace.settings {
+            collect_node_tag_names(node, &mut used_tags);
+            if let Some(category) = node.get("category").and_then(|value| value.as_str()) {
+                used_categories.insert(category.trim().to_string());
+            }
+        }
+        for template in &workspace.library.templates {
+            for tag in &template.preset.tags {
+                used_tags.insert(tag.name.trim().to_lowercase());
+            }
+        }
+    }
+
+    library.templates.retain(|template| {
+        template
+            .preset
+            .category
+            .as_deref()
+            .is_some_and(|category| used_categories.contains(category.trim()))
+            || template
+                .preset
+                .tags
+                .iter()
+                .any(|tag| used_tags.contains(&tag.name.trim().to_lowercase()))
+    });
+    for template in &library.templates {
+        for tag in &template.preset.tags {
+            used_tags.insert(tag.name.trim().to_lowercase());
+        }
+    }
+    library
+        .tags
+        .retain(|tag| used_tags.contains(&tag.name.trim().to_lowercase()));
+}
+
+fn count_tag_usage(
+    library: &SettingLibrary,
+    settings: &[&serde_json::Value],
+    templates: &[&SettingTemplate],
+) -> TagUsage {
+    let mut used = HashMap::<String, usize>::new();
+    for node in settings {
+        let mut names = HashSet::new();
+        collect_node_tag_names(node, &mut names);
+        for name in names {
+            *used.entry(name).or_default() += 1;
+        }
+    }
+    for template in templates {
+        let names = template
+            .preset
+            .tags
+            .iter()
+            .map(|tag| tag.name.trim().to_lowercase())
+            .collect::<HashSet<_>>();
+        for name in names {
+            *used.entry(name).or_default() += 1;
+        }
+    }
+
+    let mut counts = HashMap::new();
+    let mut unused = Vec::new();
+    for tag in &library.tags {
+        let count = used
+            .get(&tag.name.trim().to_lowercase())
+            .copied()
+            .unwrap_or(0);
+        if count == 0 {
+            unused.push(tag.name.clone());
+        }
+        counts.insert(tag.name.clone(), count);
+    }
+    TagUsage { counts, unused }
+}
+
+fn library_tag_usage(
+    state: &ProjectState,
+    root: &Path,
+    story_id: Option<&str>,
+) -> Result<TagUsage, String> {
+    let manifest = read_manifest(state, root)?;
+    if let Some(story_id) = story_id {
+        let Some(entry) = find_story_entry(&manifest, story_id) else {
+            return Err("故事不存在".to_string());
+        };
+        let workspace =
+            read_story_workspace(state, &story_db_path(state, root, &entry.folder_name))?;
+        let settings = workspace.settings.iter().collect::<Vec<_>>();
+        let templates = workspace.library.templates.iter().collect::<Vec<_>>();
+        return Ok(count_tag_usage(&workspace.library, &settings, &templates));
+    }
+
+    let mut workspaces = Vec::new();
+    for entry in &manifest.stories {
+        workspaces.push(read_story_workspace(
+            state,
+            &story_db_path(state, root, &entry.folder_name),
+        )?);
+    }
+    let settings = workspaces
+        .iter()
+        .flat_map(|workspace| workspace.settings.iter())
+        .collect::<Vec<_>>();
+    let templates = workspaces
+        .iter()
+        .flat_map(|workspace| workspace.library.templates.iter())
+        .chain(manifest.shared_library.templates.iter())
+        .collect::<Vec<_>>();
+    Ok(count_tag_usage(
+        &manifest.shared_library,
+        &settings,
+        &templates,
+    ))
+}
+
+fn expand_placeholders(
+    text: &str,
+    values: &HashMap<String, String>,
+    used: &mut HashSet<String>,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let key = rest[start + 2..start + 2 + length].trim();
+        output.push_str(&rest[..start]);
+        match values.get(key) {
+            Some(value) => {
+                output.push_str(value);
+                used.insert(key.to_string());
+            }
+            None => output.push_str(&rest[start..start + length + 4]),
+        }
+        rest = &rest[start + length + 4..];
+    }
+    output.push_str(rest);
+    output
+}
+
+fn expand_value_placeholders(
+    value: &mut serde_json::Value,
+    values: &HashMap<String, String>,
+    used: &mut HashSet<String>,
+) {
+    match value {
+        serde_json::Value::String(text) => *text = expand_placeholders(text, values, used),
+        serde_json::Value::Array(items) => {
+            for item in items {
+                expand_value_placeholders(item, values, used);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values_mut() {
+                expand_value_placeholders(item, values, used);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn template_node(
+    template: &SettingTemplate,
+    values: &HashMap<String, String>,
+    index: usize,
+) -> Result<(serde_json::Value, Vec<String>), String> {
+    let mut node = serde_json::to_value(&template.preset).map_err(|error| error.to_string())?;
+    let mut used = HashSet::new();
+    expand_value_placeholders(&mut node, values, &mut used);
+    let title = match values.get("name").map(|name| name.trim()) {
+        Some(name) if !name.is_empty() => {
+            used.insert("name".to_string());
+            name.to_string()
+        }
+        _ => format!("{} {index}", template.name),
+    };
+
+    let Some(map) = node.as_object_mut() else {
+        return Err("模版内容无效".to_string());
+    };
+    map.retain(|_, value| !value.is_null());
+    map.insert(
+        "id".to_string(),
+        serde_json::Value::String(Uuid::new_v4().to_string()),
+    );
+    map.insert("title".to_string(), serde_json::Value::String(title));
+    map.entry("summary")
+        .or_insert_with(|| serde_json::Value::String(String::new()));
+    map.entry("color").or_insert_with(|| {
+        serde_json::Value::String(PALETTE_COLORS[index % PALETTE_COLORS.len()].to_string())
+    });
+    map.insert(
+        "position".to_string(),
+        serde_json::json!({ "x": 120 + (index % 5) * 56, "y": 100 + (index % 4) * 56 }),
+    );
+    map.insert("relations".to_string(), serde_json::json!([]));
+
+    let mut used = used.into_iter().collect::<Vec<_>>();
+    used.sort();
+    Ok((node, used))
+}
+
+fn rename_library_category(
+    library: &mut SettingLibrary,
+    old: &str,
+    new: &str,
+    merge: bool,
+) -> Result<(), String> {
+    let old_key = category_key(old);
+    let new_key = category_key(new);
+    if !library
+        .categories
+        .iter()
+        .any(|category| category_key(category) == old_key)
+    {
+        return Err(format!("分类不存在: {old}"));
+    }
+    if old_key != new_key
+        && library
+            .categories
+            .iter()
+            .any(|category| category_key(category) == new_key)
+    {
+        if !merge {
+            return Err(format!("分类已存在: {new}"));
+        }
+        library
+            .categories
+            .retain(|category| category_key(category) != old_key);
+    } else {
+        for category in &mut library.categories {
+            if category_key(category) == old_key {
+                *category = new.to_string();
+            }
+        }
+    }
+    for template in &mut library.templates {
+        if template
+            .preset
+            .category
+            .as_deref()
+            .is_some_and(|category| category_key(category) == old_key)
+        {
+            template.preset.category = Some(new.to_string());
+        }
+    }
+    Ok(())
+}
+
+fn rename_node_categories(nodes: &mut [serde_json::Value], old: &str, new: &str) -> usize {
+    let old_key = category_key(old);
+    let mut changed = 0;
+    for node in nodes {
+        let matches = node
+            .get("category")
+            .and_then(|value| value.as_str())
+            .is_some_and(|category| category_key(category) == old_key);
+        if let (true, Some(map)) = (matches, node.as_object_mut()) {
+            map.insert(
+                "category".to_string(),
+                serde_json::Value::String(new.to_string()),
+            );
+            changed += 1;
+        }
+    }
+    changed
+}
+
+fn update_library_in_place<T>(
+    state: &ProjectState,
+    root: &Path,
+    story_id: Option<&str>,
+    update: impl FnOnce(&mut SettingLibrary) -> Result<T, String>,
+) -> Result<T, String> {
+    let story_lock = story_id.map(|story_id| story_write_lock(state, story_id));
+    let _story_guard = story_lock
+        .as_ref()
+        .map(|lock| lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    let _manifest_guard = manifest_write_lock(state);
+    let mut manifest = read_manifest(state, root)?;
+    let Some(story_id) = story_id else {
+        let result = update(&mut manifest.shared_library)?;
+        write_manifest(state, root, &manifest)?;
+        return Ok(result);
+    };
+
+    let Some(entry) = find_story_entry_mut(&mut manifest, story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    let db_path = story_db_path(state, root, &entry.folder_name);
+    let mut workspace = read_workspace(&db_path)?;
+    let result = update(&mut workspace.library)?;
+    write_workspace(&db_path, &workspace)?;
+    entry.story.updated_at = now_rfc3339();
+    write_manifest(state, root, &manifest)?;
+    Ok(result)
+}
+
+fn dir_size(path: &Path, excluded: &[&str]) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter(|entry| !excluded.contains(&entry.file_name().to_string_lossy().as_ref()))
+        .map(|entry| match fs::symlink_metadata(entry.path()) {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path(), &[]),
+            Ok(meta) if meta.is_file() => meta.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+fn ensure_free_space(target_dir: &Path, required: u64) -> Result<(), String> {
+    let available = fs2::available_space(target_dir)
+        .map_err(|error| format!("读取磁盘可用空间失败: {error}"))?;
+    if available < required {
+        return Err(format!(
+            "磁盘空间不足: 需要 {}，可用 {}",
+            format_bytes(required),
+            format_bytes(available)
+        ));
+    }
+    Ok(())
+}
+
+fn project_display_name(root: &Path) -> String {
+    root.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .map(|name| name.trim_end_matches(".takecopter").to_string())
+        .unwrap_or_else(|| "takecopter".to_string())
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned = name
+        .chars()
+        .map(|ch| {
+            if ch.is_control() || matches!(ch, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+            {
+                '-'
+            } else {
+                ch
+            }
+        })
+        .collect::<String>();
+    cleaned.trim().trim_matches('.').trim().to_string()
+}
+
+fn staging_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{file_name}.tmp"))
+}
+
+fn write_file_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let staging = staging_path(path);
+    let result = fs::File::create(&staging)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&staging, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&staging);
+    }
+    result
+}
+
+fn render_export_file_name(
+    filename_template: Option<&str>,
+    name: &str,
+    export_dir: &Path,
+    extension: &str,
+    default_name: String,
+) -> String {
+    let Some(template) = filename_template
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    else {
+        return default_name;
+    };
+
+    let now = Utc::now();
+    // {count} is the next sequence number among files already in the exports folder.
+    let count = fs::read_dir(export_dir)
+        .map(|entries| entries.flatten().count() + 1)
+        .unwrap_or(1);
+    let rendered = template
+        .replace("{name}", name)
+        .replace("{date}", &now.format("%Y%m%d").to_string())
+        .replace("{time}", &now.format("%H%M%S").to_string())
+        .replace("{count}", &count.to_string());
+    let rendered = sanitize_file_name(rendered.trim_end_matches(extension));
+    if rendered.is_empty() {
+        return default_name;
+    }
+    format!("{rendered}{extension}")
+}
+
+fn export_kind(name: &str, is_dir: bool) -> &'static str {
+    if is_dir {
+        return if name.starts_with("backup-") {
+            "backup"
+        } else {
+            "other"
+        };
+    }
+    if name.ends_with(".enc") {
+        "encrypted"
+    } else if name.starts_with("takecopter-project-") {
+        "project"
+    } else if name.starts_with("takecopter-stories-") {
+        "stories"
+    } else if name.starts_with("takecopter-story-") {
+        "story"
+    } else if name.starts_with("takecopter-library-") {
+        "library"
+    } else if name.ends_with(".json") {
+        "json"
+    } else {
+        "other"
+    }
+}
+
+fn list_export_entries(export_dir: &Path) -> Vec<ExportInfo> {
+    let Ok(entries) = fs::read_dir(export_dir) else {
+        return Vec::new();
+    };
+    let mut exports = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || name.ends_with(".tmp") {
+                return None;
+            }
+            let meta = entry.metadata().ok()?;
+            Some(ExportInfo {
+                kind: export_kind(&name, meta.is_dir()).to_string(),
+                size_bytes: if meta.is_dir() {
+                    dir_size(&entry.path(), &[])
+                } else {
+                    meta.len()
+                },
+                modified_at: meta.modified().ok().map(|modified| {
+                    chrono::DateTime::<Utc>::from(modified)
+                        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+                }),
+                name,
+            })
+        })
+        .collect::<Vec<_>>();
+    exports.sort_by(|a, b| {
+        b.modified_at
+            .cmp(&a.modified_at)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    exports
+}
+
+fn compute_project_size(state: &ProjectState, root: &Path) -> Result<ProjectSize, String> {
+    let manifest = read_manifest(state, root)?;
+    let stories = manifest
+        .stories
+        .iter()
+        .map(|entry| {
+            let db_bytes = fs::metadata(story_db_path(state, root, &entry.folder_name))
+                .map(|meta| meta.len())
+                .unwrap_or(0);
+            let assets_bytes = dir_size(
+                &story_root(state, root, &entry.folder_name).join("assets"),
+                &[],
+            );
+            StorySize {
+                story_id: entry.story.id.clone(),
+                title: entry.story.title.clone(),
+                db_bytes,
+                assets_bytes,
+                total_bytes: db_bytes + assets_bytes,
+                total_human: format_bytes(db_bytes + assets_bytes),
+            }
+        })
+        .collect();
+    let total_bytes = dir_size(
+        root,
+        &[
+            exports_folder_name(&layout_config(state, root)).as_str(),
+            ".trash",
+        ],
+    );
+    Ok(ProjectSize {
+        total_bytes,
+        total_human: format_bytes(total_bytes),
+        stories,
+    })
+}
+
+#[tauri::command]
+pub fn get_bootstrap_state(
+    app: AppHandle,
+    state: State<ProjectState>,
+) -> Result<BootstrapState, String> {
+    let default_root = default_root_path(&app)?;
+    let active_root = resolve_state_root(&app, &state)?;
+    let schema_version = active_root
+        .as_deref()
+        .filter(|root| project_manifest_path(root).exists())
+        .and_then(|root| read_manifest(&state, root).ok())
+        .map(|manifest| manifest.schema_version);
+
+    Ok(BootstrapState {
+        needs_setup: active_root.is_none(),
+        default_root_path: default_root.to_string_lossy().to_string(),
+        active_root_path: active_root.map(|item| item.to_string_lossy().to_string()),
+        schema_version,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        needs_upgrade: schema_version.is_some_and(|version| version > CURRENT_SCHEMA_VERSION),
+        read_only: is_read_only(&state),
+    })
+}
+
+#[tauri::command]
+pub fn pick_project_root() -> Result<Option<String>, String> {
+    let selected = rfd::FileDialog::new()
+        .set_title("选择故事项目目录")
+        .pick_folder();
+    Ok(selected.map(|path| path.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+pub fn initialize_project_root(
+    app: AppHandle,
+    state: State<ProjectState>,
+    root_path: Option<String>,
+) -> Result<(), String> {
+    let target = if let Some(path) = root_path {
+        let trimmed = path.trim();
+        if trimmed.is_empty() {
+            default_root_path(&app)?
+        } else {
+            PathBuf::from(trimmed)
+        }
+    } else {
+        default_root_path(&app)?
+    };
+
+    fs::create_dir_all(&target).map_err(|error| format!("无法创建项目目录: {error}"))?;
+    ensure_root_writable(&target)?;
+    ensure_root_layout(&state, &target)?;
+    set_active_root(&app, &state, &target)
+}
+
+#[tauri::command]
+pub fn open_project_root(
+    app: AppHandle,
+    state: State<ProjectState>,
+    root_path: String,
+) -> Result<(), String> {
+    open_root(&app, &state, &PathBuf::from(root_path.trim()))
+}
+
+#[tauri::command]
+pub fn ensure_project(
+    app: AppHandle,
+    state: State<ProjectState>,
+    include_archived: Option<bool>,
+) -> Result<EnsureProjectResponse, String> {
+    let root = require_active_root(&app, &state)?;
+    let read_only = is_read_only(&state);
+    if !read_only {
+        ensure_root_layout(&state, &root)?;
+        start_lock_heartbeat(&state, &root)?;
+    }
+    let (mut data, manifest_warning) = load_project_data_with_warning(&state, &root)?;
+    record_manifest_mtime(&state, &root);
+    start_project_watcher(&app, &state, &root)?;
+    if !read_only {
+        start_auto_backup(&app, &state, &root);
+    }
+
+    let mut session = read_session(&root);
+    if let Some(last_story_id) = session.last_story_id.as_deref() {
+        if !data.stories.iter().any(|story| story.id == last_story_id) {
+            session.last_story_id = None;
+            if !read_only {
+                write_session(&root, &session)?;
+            }
+        }
+    }
+
+    let mut recovered_story_ids = data
+        .workspaces
+        .iter()
+        .filter(|(_, workspace)| workspace.recovered)
+        .map(|(story_id, _)| story_id.clone())
+        .collect::<Vec<_>>();
+    recovered_story_ids.sort();
+
+    if !include_archived.unwrap_or(false) {
+        let archived_ids = data
+            .stories
+            .iter()
+            .filter(|story| story.archived)
+            .map(|story| story.id.clone())
+            .collect::<HashSet<_>>();
+        data.stories.retain(|story| !story.archived);
+        data.workspaces
+            .retain(|story_id, _| !archived_ids.contains(story_id));
+    }
+
+    Ok(EnsureProjectResponse {
+        project_path: root.to_string_lossy().to_string(),
+        data,
+        last_story_id: session.last_story_id,
+        recovered_story_ids,
+        manifest_warning,
+    })
+}
+
+#[tauri::command]
+pub fn create_story(
+    app: AppHandle,
+    state: State<ProjectState>,
+    input: CreateStoryInput,
+) -> Result<CreateStoryResult, String> {
+    let title = clean_story_title(&input.title)?;
+    let root = require_writable_root(&app, &state)?;
+    ensure_root_layout(&state, &root)?;
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+
+    let id = Uuid::new_v4().to_string();
+    let now = now_rfc3339();
+    let cover_color = match input.cover_color.as_deref() {
+        Some(color) => validate_cover_color(&state, &root, color)?,
+        None => default_cover_color(&id).to_string(),
+    };
+    let story = Story {
+        id: id.clone(),
+        title,
+        description: input.description,
+        updated_at: now,
+        cover_color,
+        pinned: false,
+        archived: false,
+    };
+    let folder_name = free_story_folder_name(&state, &root, &manifest, &story.title, &story.id);
+
+    let workspace = Workspace {
+        settings: vec![],
+        tree: vec![],
+        library: default_library(),
+        repaired: false,
+        recovered: false,
+    };
+    write_workspace(&story_db_path(&state, &root, &folder_name), &workspace)?;
+
+    manifest.stories.push(StoryManifestEntry {
+        story: story.clone(),
+        folder_name,
+        order: None,
+    });
+    write_manifest(&state, &root, &manifest)?;
+    let _ = append_activity(&root, &story.id, "create_story");
+
+    Ok(CreateStoryResult { story, workspace })
+}
+
+#[tauri::command]
+pub fn rename_story(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    title: String,
+) -> Result<Story, String> {
+    let clean_title = clean_story_title(&title)?;
+
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let next_folder_name =
+        free_story_folder_name(&state, &root, &manifest, &clean_title, &story_id);
+    let (updated_story, old_title, old_folder_name) = {
+        let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+            return Err("故事不存在".to_string());
+        };
+
+        let old_folder_name = entry.folder_name.clone();
+        let old_title = entry.story.title.clone();
+
+        if old_folder_name != next_folder_name {
+            let old_path = story_root(&state, &root, &old_folder_name);
+            let next_path = story_root(&state, &root, &next_folder_name);
+            if old_path.exists() {
+                if next_path.exists() {
+                    return Err("目标故事目录已存在，请使用其他名称".to_string());
+                }
+                fs::rename(&old_path, &next_path)
+                    .map_err(|error| format!("重命名故事目录失败: {error}"))?;
+                migrate_story_asset_references(&state, &root, &old_folder_name, &next_folder_name)?;
+            }
+            entry.folder_name = next_folder_name;
+        }
+
+        entry.story.title = clean_title.clone();
+        entry.story.updated_at = now_rfc3339();
+        (entry.story.clone(), old_title, old_folder_name)
+    };
+
+    write_manifest(&state, &root, &manifest)?;
+    push_undo(
+        &state,
+        UndoEntry::RenameStory {
+            story_id: story_id.clone(),
+            title: old_title,
+            folder_name: old_folder_name,
+        },
+    );
+    let _ = append_activity(&root, &story_id, "rename_story");
+    Ok(updated_story)
+}
+
+#[tauri::command]
+pub fn delete_story(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<(), String> {
+    let root = require_writable_root(&app, &state)?;
+    trash_story(&state, &root, &story_id)?;
+    let _ = append_activity(&root, &story_id, "delete_story");
+    Ok(())
+}
+
+// Moves the whole story folder (db, assets, history) into `.trash` so undo can bring all of
+// it back, not just the workspace.
+fn trash_story(state: &ProjectState, root: &Path, story_id: &str) -> Result<(), String> {
+    let story_lock = story_write_lock(state, story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(state);
+    let mut manifest = read_manifest(state, root)?;
+
+    let index = manifest
+        .stories
+        .iter()
+        .position(|item| item.story.id == story_id)
+        .ok_or_else(|| "故事不存在".to_string())?;
+
+    let folder_path = story_root(state, root, &manifest.stories[index].folder_name);
+    let trash_path = trash_root(root).join(story_id);
+    if trash_path.exists() {
+        fs::remove_dir_all(&trash_path).map_err(|error| format!("清理回收站失败: {error}"))?;
+    }
+    fs::create_dir_all(trash_root(root)).map_err(|error| format!("创建回收站失败: {error}"))?;
+    if folder_path.exists() {
+        fs::rename(&folder_path, &trash_path)
+            .map_err(|error| format!("删除故事目录失败: {error}"))?;
+    } else {
+        fs::create_dir_all(&trash_path).map_err(|error| format!("创建回收站失败: {error}"))?;
+    }
+
+    let entry = manifest.stories.remove(index);
+    if let Err(error) = write_manifest(state, root, &manifest) {
+        let _ = fs::rename(&trash_path, &folder_path);
+        return Err(error);
+    }
+    push_undo(
+        state,
+        UndoEntry::DeleteStory {
+            index,
+            entry,
+            trash_path,
+        },
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub fn update_settings(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    settings: Vec<serde_json::Value>,
+) -> Result<(), String> {
+    validate_settings(&settings)?;
+    let root = require_writable_root(&app, &state)?;
+    write_story_settings(&state, &root, &story_id, settings)
+}
+
+#[tauri::command]
+pub fn update_tree(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    tree: Vec<serde_json::Value>,
+) -> Result<(), String> {
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let _ = auto_snapshot_story(&state, &root, &entry.folder_name);
+    let current = read_workspace(&story_db_path(&state, &root, &entry.folder_name))?;
+    let next = Workspace {
+        settings: current.settings,
+        tree,
+        library: current.library,
+        repaired: false,
+        recovered: false,
+    };
+    write_workspace(&story_db_path(&state, &root, &entry.folder_name), &next)?;
+
+    entry.story.updated_at = now_rfc3339();
+    write_manifest(&state, &root, &manifest)?;
+    let _ = append_activity(&root, &story_id, "update_tree");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn update_story_library(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    mut library: SettingLibrary,
+) -> Result<bool, String> {
+    validate_library(&library)?;
+    let normalized = normalize_categories(&mut library.categories);
+    let root = require_writable_root(&app, &state)?;
+    validate_template_types(&state, &root, &library)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let mut current = read_workspace(&story_db_path(&state, &root, &entry.folder_name))?;
+    let previous = std::mem::replace(&mut current.library, library);
+    write_workspace(&story_db_path(&state, &root, &entry.folder_name), &current)?;
+
+    entry.story.updated_at = now_rfc3339();
+    write_manifest(&state, &root, &manifest)?;
+    push_undo(
+        &state,
+        UndoEntry::StoryLibrary {
+            story_id,
+            library: previous,
+        },
+    );
+    Ok(normalized)
+}
+
+#[tauri::command]
+pub fn update_global_library(
+    app: AppHandle,
+    state: State<ProjectState>,
+    library: SettingLibrary,
+) -> Result<(), String> {
+    validate_library(&library)?;
+    let root = require_writable_root(&app, &state)?;
+    validate_template_types(&state, &root, &library)?;
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let previous = std::mem::replace(&mut manifest.shared_library, library);
+    write_manifest(&state, &root, &manifest)?;
+    push_undo(&state, UndoEntry::GlobalLibrary { library: previous });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn dedupe_library(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: Option<String>,
+) -> Result<SettingLibrary, String> {
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_id
+        .as_deref()
+        .map(|story_id| story_write_lock(&state, story_id));
+    let _story_guard = story_lock
+        .as_ref()
+        .map(|lock| lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+
+    let Some(story_id) = story_id else {
+        let mut library = manifest.shared_library.clone();
+        if dedupe_library_entries(&mut library) {
+            manifest.shared_library = library.clone();
+            write_manifest(&state, &root, &manifest)?;
+        }
+        return Ok(library);
+    };
+
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    let db_path = story_db_path(&state, &root, &entry.folder_name);
+    let mut current = read_workspace(&db_path)?;
+    if dedupe_library_entries(&mut current.library) {
+        write_workspace(&db_path, &current)?;
+        entry.story.updated_at = now_rfc3339();
+        write_manifest(&state, &root, &manifest)?;
+    }
+    Ok(current.library)
+}
+
+#[tauri::command]
+pub fn export_project(
+    app: AppHandle,
+    state: State<ProjectState>,
+) -> Result<ExportedProjectData, String> {
+    let root = require_active_root(&app, &state)?;
+    let data = load_project_data(&state, &root)?;
+
+    Ok(ExportedProjectData {
+        app: "takecopter".to_string(),
+        schema_version: CURRENT_SCHEMA_VERSION,
+        exported_at: now_rfc3339(),
+        data,
+    })
+}
+
+#[tauri::command]
+pub fn export_story(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    include_assets: Option<bool>,
+) -> Result<ExportedStoryData, String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    story_export_payload(&state, &root, entry, include_assets.unwrap_or(false))
+}
+
+fn story_export_payload(
+    state: &ProjectState,
+    root: &Path,
+    entry: &StoryManifestEntry,
+    include_assets: bool,
+) -> Result<ExportedStoryData, String> {
+    let db_path = story_db_path(state, root, &entry.folder_name);
+    let workspace = read_story_workspace(state, &db_path)?;
+    let (assets, missing_assets) = if include_assets {
+        embed_story_assets(&story_root(state, root, &entry.folder_name), &workspace)
+    } else {
+        (HashMap::new(), Vec::new())
+    };
+    Ok(ExportedStoryData {
+        app: "takecopter".to_string(),
+        schema_version: CURRENT_SCHEMA_VERSION,
+        exported_at: now_rfc3339(),
+        story: entry.story.clone(),
+        workspace,
+        workspace_updated_at: workspace_updated_at(&db_path, is_read_only(state)),
+        folder_name: Some(entry.folder_name.clone()),
+        assets,
+        missing_assets,
+    })
+}
+
+#[tauri::command]
+pub fn export_project_to_local(
+    app: AppHandle,
+    state: State<ProjectState>,
+    filename_template: Option<String>,
+    open_after: Option<bool>,
+) -> Result<String, String> {
+    let root = require_active_root(&app, &state)?;
+    let export_dir = exports_root(&state, &root);
+    let payload = export_project(app, state)?;
+    fs::create_dir_all(&export_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
+    let file_path = export_dir.join(render_export_file_name(
+        filename_template.as_deref(),
+        &project_display_name(&root),
+        &export_dir,
+        ".json",
+        format!(
+            "takecopter-project-{}.json",
+            Utc::now().format("%Y%m%d-%H%M%S")
+        ),
+    ));
+    let raw = serde_json::to_vec_pretty(&payload).map_err(|error| error.to_string())?;
+    ensure_free_space(&export_dir, raw.len() as u64)?;
+    write_file_atomically(&file_path, &raw)
+        .map_err(|error| format!("写入导出文件失败: {error}"))?;
+    if open_after.unwrap_or(true) {
+        open_path_in_file_manager(&export_dir)?;
+    }
+    Ok(file_path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn export_story_to_local(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    filename_template: Option<String>,
+    open_after: Option<bool>,
+) -> Result<String, String> {
+    let root = require_active_root(&app, &state)?;
+    let export_dir = exports_root(&state, &root);
+    let payload = export_story(app, state, story_id, None)?;
+    fs::create_dir_all(&export_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
+    let file_path = export_dir.join(render_export_file_name(
+        filename_template.as_deref(),
+        &payload.story.title,
+        &export_dir,
+        ".json",
+        format!(
+            "takecopter-story-{}-{}.json",
+            payload.story.id,
+            Utc::now().format("%Y%m%d-%H%M%S")
+        ),
+    ));
+    let raw = serde_json::to_vec_pretty(&payload).map_err(|error| error.to_string())?;
+    write_file_atomically(&file_path, &raw)
+        .map_err(|error| format!("写入导出文件失败: {error}"))?;
+    if open_after.unwrap_or(true) {
+        open_path_in_file_manager(&export_dir)?;
+    }
+    Ok(file_path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn backup_local_database(
+    app: AppHandle,
+    state: State<ProjectState>,
+    backup_dir: Option<String>,
+    open_after: Option<bool>,
+) -> Result<String, String> {
+    let root = require_active_root(&app, &state)?;
+    let backup_dir = create_backup(&state, &root, backup_dir.as_deref())?;
+    if open_after.unwrap_or(true) {
+        open_path_in_file_manager(&backup_dir)?;
+    }
+    Ok(backup_dir.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn import_project(
+    app: AppHandle,
+    state: State<ProjectState>,
+    payload: serde_json::Value,
+    allow_newer: Option<bool>,
+) -> Result<ImportResult, String> {
+    import_project_value(&app, &state, payload, allow_newer.unwrap_or(false))
+}
+
+fn import_project_value(
+    app: &AppHandle,
+    state: &ProjectState,
+    raw: serde_json::Value,
+    allow_newer: bool,
+) -> Result<ImportResult, String> {
+    let mut payload = serde_json::from_value::<ExportedProjectData>(raw.clone())
+        .map_err(|error| format!("解析项目文件失败: {error}"))?;
+    if payload.app != "takecopter" {
+        return Err("无效的项目文件来源".to_string());
+    }
+
+    let mut warning = ImportWarning::default();
+    if payload.schema_version > CURRENT_SCHEMA_VERSION {
+        if !allow_newer {
+            return Err("项目版本过新，请升级应用后再导入".to_string());
+        }
+        warning.newer_schema_version = Some(payload.schema_version);
+        if let Ok(parsed) = serde_json::to_value(&payload) {
+            collect_dropped_fields(&raw, &parsed, "", &mut warning.dropped_fields);
+            warning.dropped_fields.sort();
+            warning.dropped_fields.dedup();
+        }
+        if !warning.dropped_fields.is_empty() {
+            log::warn!(
+                "导入较新版本项目时忽略了以下字段: {}",
+                warning.dropped_fields.join(", ")
+            );
+        }
+    }
+    for story in &payload.data.stories {
+        validate_imported_story(story)?;
+    }
+    payload.exported_at =
+        normalize_import_timestamp(&payload.exported_at, &mut warning.invalid_timestamps);
+    for story in &mut payload.data.stories {
+        story.updated_at =
+            normalize_import_timestamp(&story.updated_at, &mut warning.invalid_timestamps);
+    }
+
+    let story_ids = payload
+        .data
+        .stories
+        .iter()
+        .map(|story| story.id.as_str())
+        .collect::<HashSet<_>>();
+    warning.stories_without_workspace = payload
+        .data
+        .stories
+        .iter()
+        .filter(|story| !payload.data.workspaces.contains_key(&story.id))
+        .map(|story| story.id.clone())
+        .collect();
+    warning.orphaned_workspaces = payload
+        .data
+        .workspaces
+        .keys()
+        .filter(|story_id| !story_ids.contains(story_id.as_str()))
+        .cloned()
+        .collect();
+    warning.orphaned_workspaces.sort();
+
+    let root = require_writable_root(app, state)?;
+    import_project_payload(state, &root, payload, warning)
+}
+
+fn import_project_payload(
+    state: &ProjectState,
+    root: &Path,
+    payload: ExportedProjectData,
+    warning: ImportWarning,
+) -> Result<ImportResult, String> {
+    ensure_root_layout(state, root)?;
+    flush_pending_settings(state)?;
+    let story_locks = story_write_locks(
+        state,
+        read_manifest(state, root)?
+            .stories
+            .into_iter()
+            .map(|entry| entry.story.id)
+            .chain(payload.data.stories.iter().map(|story| story.id.clone())),
+    );
+    let _story_guards = story_locks
+        .iter()
+        .map(|lock| lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+        .collect::<Vec<_>>();
+    let _manifest_guard = manifest_write_lock(state);
+
+    let mut manifest = read_manifest(state, root)?;
+    let existing_ids = manifest
+        .stories
+        .iter()
+        .map(|entry| entry.story.id.clone())
+        .collect::<HashSet<_>>();
+    manifest.shared_library = payload.data.shared_library.clone();
+    sanitize_library_asset_references(&mut manifest.shared_library);
+    let previous = std::mem::take(&mut manifest.stories);
+    for story in &payload.data.stories {
+        let reusable = previous
+            .iter()
+            .find(|entry| entry.story.id == story.id)
+            .map(|entry| entry.folder_name.clone())
+            .filter(|folder_name| {
+                !manifest
+                    .stories
+                    .iter()
+                    .any(|entry| &entry.folder_name == folder_name)
+            });
+        let folder_name = reusable.unwrap_or_else(|| {
+            free_story_folder_name(state, root, &manifest, &story.title, &story.id)
+        });
+        manifest.stories.push(StoryManifestEntry {
+            story: story.clone(),
+            folder_name,
+            order: None,
+        });
+    }
+    write_manifest(state, root, &manifest)?;
+
+    for entry in &manifest.stories {
+        let mut workspace = payload
+            .data
+            .workspaces
+            .get(&entry.story.id)
+            .cloned()
+            .unwrap_or(Workspace {
+                settings: vec![],
+                tree: vec![],
+                library: default_library(),
+                repaired: false,
+                recovered: false,
+            });
+        sanitize_workspace_asset_references(&mut workspace);
+        write_workspace(&story_db_path(state, root, &entry.folder_name), &workspace)?;
+    }
+
+    let has_warning = warning.newer_schema_version.is_some()
+        || !warning.dropped_fields.is_empty()
+        || !warning.stories_without_workspace.is_empty()
+        || !warning.orphaned_workspaces.is_empty()
+        || !warning.invalid_timestamps.is_empty();
+    Ok(ImportResult {
+        imported_story_ids: manifest
+            .stories
+            .iter()
+            .map(|entry| entry.story.id.clone())
+            .collect(),
+        overwritten_story_ids: manifest
+            .stories
+            .iter()
+            .map(|entry| entry.story.id.clone())
+            .filter(|story_id| existing_ids.contains(story_id))
+            .collect(),
+        warning: has_warning.then_some(warning),
+        deduplicated_bytes: 0,
+    })
+}
+
+#[tauri::command]
+pub fn import_story(
+    app: AppHandle,
+    state: State<ProjectState>,
+    payload: ExportedStoryData,
+) -> Result<ImportResult, String> {
+    let root = require_writable_root(&app, &state)?;
+    import_story_payload(&state, &root, payload)
+}
+
+fn import_story_payload(
+    state: &ProjectState,
+    root: &Path,
+    mut payload: ExportedStoryData,
+) -> Result<ImportResult, String> {
+    if payload.app != "takecopter" {
+        return Err("无效的故事文件来源".to_string());
+    }
+    if payload.schema_version > CURRENT_SCHEMA_VERSION {
+        return Err("故事版本过新，请升级应用后再导入".to_string());
+    }
+    validate_imported_story(&payload.story)?;
+    let mut invalid_timestamps = Vec::new();
+    payload.exported_at = normalize_import_timestamp(&payload.exported_at, &mut invalid_timestamps);
+    payload.story.updated_at =
+        normalize_import_timestamp(&payload.story.updated_at, &mut invalid_timestamps);
+    payload.workspace_updated_at = payload
+        .workspace_updated_at
+        .as_deref()
+        .map(|raw| normalize_import_timestamp(raw, &mut invalid_timestamps));
+
+    let story_lock = story_write_lock(state, &payload.story.id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    ensure_root_layout(state, root)?;
+
+    let _manifest_guard = manifest_write_lock(state);
+
+    let mut manifest = read_manifest(state, root)?;
+    let overwritten = find_story_entry(&manifest, &payload.story.id).is_some();
+    let folder_name = if let Some(existing) = find_story_entry(&manifest, &payload.story.id) {
+        existing.folder_name.clone()
+    } else {
+        free_story_folder_name(
+            state,
+            root,
+            &manifest,
+            &payload.story.title,
+            &payload.story.id,
+        )
+    };
+
+    if let Some(entry) = find_story_entry_mut(&mut manifest, &payload.story.id) {
+        entry.story = payload.story.clone();
+        entry.folder_name = folder_name.clone();
+    } else {
+        manifest.stories.push(StoryManifestEntry {
+            story: payload.story.clone(),
+            folder_name: folder_name.clone(),
+            order: None,
+        });
+    }
+
+    write_manifest(state, root, &manifest)?;
+    let source_folder = payload.folder_name.clone();
+    let rewritten = rewrite_workspace_asset_references(&mut payload.workspace, &|reference| {
+        relative_asset_reference(reference, source_folder.as_deref())
+    });
+    if rewritten > 0 {
+        log::info!("导入时改写了 {rewritten} 个素材路径");
+    }
+    sanitize_workspace_asset_references(&mut payload.workspace);
+    let (remapped, deduplicated_bytes) =
+        materialize_story_assets(&story_root(state, root, &folder_name), &payload.assets)?;
+    rewrite_workspace_asset_references(&mut payload.workspace, &|reference| {
+        remapped.get(reference).cloned()
+    });
+    let touched_at = payload
+        .workspace_updated_at
+        .clone()
+        .unwrap_or_else(now_rfc3339);
+    write_workspace_touched_at(
+        &story_db_path(state, root, &folder_name),
+        &payload.workspace,
+        &touched_at,
+    )?;
+
+    Ok(ImportResult {
+        imported_story_ids: vec![payload.story.id.clone()],
+        overwritten_story_ids: if overwritten {
+            vec![payload.story.id]
+        } else {
+            vec![]
+        },
+        warning: (!invalid_timestamps.is_empty()).then(|| ImportWarning {
+            invalid_timestamps,
+            ..ImportWarning::default()
+        }),
+        deduplicated_bytes,
+    })
+}
+
+#[tauri::command]
+pub fn open_story_folder(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<(), String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    open_path_in_file_manager(&story_root(&state, &root, &entry.folder_name))
+}
+
+#[tauri::command]
+pub fn open_story_database(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<(), String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    open_path_in_file_manager(&story_db_path(&state, &root, &entry.folder_name))
+}
+
+#[tauri::command]
+pub fn compute_backlinks(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<HashMap<String, Vec<String>>, String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    let workspace =
+        read_story_workspace(&state, &story_db_path(&state, &root, &entry.folder_name))?;
+    Ok(compute_setting_backlinks(&workspace.settings))
+}
+
+#[tauri::command]
+pub fn find_replace(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    find: String,
+    replace: String,
+    options: Option<FindReplaceOptions>,
+) -> Result<ReplaceResult, String> {
+    if find.is_empty() {
+        return Err("查找内容不能为空".to_string());
+    }
+    let options = options.unwrap_or_default();
+
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let db_path = story_db_path(&state, &root, &entry.folder_name);
+    let mut workspace = read_workspace(&db_path)?;
+    let mut result = ReplaceResult {
+        dry_run: options.dry_run,
+        ..ReplaceResult::default()
+    };
+    for node in &mut workspace.settings {
+        replace_in_node(node, &find, &replace, &options, &mut result, false);
+    }
+    for node in &mut workspace.tree {
+        replace_in_node(node, &find, &replace, &options, &mut result, true);
+    }
+
+    if options.dry_run || result.replacements == 0 {
+        return Ok(result);
+    }
+
+    write_workspace(&db_path, &workspace)?;
+    entry.story.updated_at = now_rfc3339();
+    write_manifest(&state, &root, &manifest)?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn get_config(app: AppHandle, state: State<ProjectState>) -> Result<ProjectConfig, String> {
+    let root = require_active_root(&app, &state)?;
+    project_config(&state, &root)
+}
+
+#[tauri::command]
+pub fn update_config(
+    app: AppHandle,
+    state: State<ProjectState>,
+    config: ProjectConfig,
+) -> Result<(), String> {
+    for name in [config.stories_dir.as_deref(), config.exports_dir.as_deref()]
+        .into_iter()
+        .flatten()
+    {
+        if !is_valid_folder_name(name) {
+            return Err(format!("无效的目录名称: {name}"));
+        }
+    }
+    if stories_folder_name(&config) == exports_folder_name(&config) {
+        return Err("故事目录与导出目录不能相同".to_string());
+    }
+    let root = require_writable_root(&app, &state)?;
+    flush_pending_settings(&state)?;
+    let current = project_config(&state, &root)?;
+    let moved = move_layout_folders(&root, &current, &config)?;
+    if let Err(error) = write_config(&state, &root, &config) {
+        rollback_folder_moves(&moved);
+        return Err(error);
+    }
+    ensure_root_layout(&state, &root)?;
+    if stories_folder_name(&current) != stories_folder_name(&config) {
+        stop_project_watcher(&state);
+        start_project_watcher(&app, &state, &root)?;
+    }
+    start_auto_backup(&app, &state, &root);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_workspace(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<Workspace, String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    let workspace =
+        read_story_workspace(&state, &story_db_path(&state, &root, &entry.folder_name))?;
+
+    let mut session = read_session(&root);
+    if !is_read_only(&state) && session.last_story_id.as_deref() != Some(story_id.as_str()) {
+        session.last_story_id = Some(story_id);
+        write_session(&root, &session)?;
+    }
+    Ok(workspace)
+}
+
+#[tauri::command]
+pub fn export_project_encrypted(
+    app: AppHandle,
+    state: State<ProjectState>,
+    password: String,
+) -> Result<String, String> {
+    if password.is_empty() {
+        return Err("导出密码不能为空".to_string());
+    }
+
+    let root = require_active_root(&app, &state)?;
+    let export_dir = exports_root(&state, &root);
+    let payload = export_project(app, state)?;
+    fs::create_dir_all(&export_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
+    let file_path = export_dir.join(format!(
+        "takecopter-project-{}.takecopter.enc",
+        Utc::now().format("%Y%m%d-%H%M%S")
+    ));
+    let plaintext = serde_json::to_vec(&payload).map_err(|error| error.to_string())?;
+    let raw = encrypt_export(&password, &plaintext)?;
+    write_file_atomically(&file_path, &raw)
+        .map_err(|error| format!("写入导出文件失败: {error}"))?;
+    Ok(file_path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn import_project_encrypted(
+    app: AppHandle,
+    state: State<ProjectState>,
+    path: String,
+    password: String,
+) -> Result<ImportResult, String> {
+    let raw = fs::read(path.trim()).map_err(|error| format!("读取加密文件失败: {error}"))?;
+    let plaintext = decrypt_export(&password, &raw)?;
+    let payload = serde_json::from_slice::<serde_json::Value>(&plaintext)
+        .map_err(|error| format!("解析项目文件失败: {error}"))?;
+    import_project_value(&app, &state, payload, false)
+}
+
+#[tauri::command]
+pub fn diff_exports(a_path: String, b_path: String) -> Result<ProjectDiff, String> {
+    let before = read_project_export_file(&a_path)?;
+    let after = read_project_export_file(&b_path)?;
+    Ok(diff_project_data(&before.data, &after.data))
+}
+
+#[tauri::command]
+pub fn merge_project_file(
+    app: AppHandle,
+    state: State<ProjectState>,
+    path: String,
+    strategy: Option<MergeStrategy>,
+) -> Result<MergeReport, String> {
+    let strategy = strategy.unwrap_or_default();
+    let mut payload = read_project_export_file(&path)?;
+    if payload.schema_version > CURRENT_SCHEMA_VERSION {
+        return Err("项目版本过新，请升级应用后再导入".to_string());
+    }
+    for story in &payload.data.stories {
+        validate_imported_story(story)?;
+    }
+    let mut invalid_timestamps = Vec::new();
+    payload.exported_at = normalize_import_timestamp(&payload.exported_at, &mut invalid_timestamps);
+    for story in &mut payload.data.stories {
+        story.updated_at = normalize_import_timestamp(&story.updated_at, &mut invalid_timestamps);
+    }
+
+    let root = require_writable_root(&app, &state)?;
+    merge_project_payload(&state, &root, payload, strategy, invalid_timestamps)
+}
+
+fn merge_project_payload(
+    state: &ProjectState,
+    root: &Path,
+    payload: ExportedProjectData,
+    strategy: MergeStrategy,
+    invalid_timestamps: Vec<String>,
+) -> Result<MergeReport, String> {
+    ensure_root_layout(state, root)?;
+    flush_pending_settings(state)?;
+    let story_locks = story_write_locks(
+        state,
+        payload.data.stories.iter().map(|story| story.id.clone()),
+    );
+    let _story_guards = story_locks
+        .iter()
+        .map(|lock| lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+        .collect::<Vec<_>>();
+    let _manifest_guard = manifest_write_lock(state);
+    let mut manifest = read_manifest(state, root)?;
+    let mut incoming_library = payload.data.shared_library.clone();
+    sanitize_library_asset_references(&mut incoming_library);
+    let mut report = MergeReport {
+        shared_library_changed: merge_library(&mut manifest.shared_library, &incoming_library),
+        invalid_timestamps,
+        ..MergeReport::default()
+    };
+
+    for story in &payload.data.stories {
+        let mut workspace = payload
+            .data
+            .workspaces
+            .get(&story.id)
+            .cloned()
+            .unwrap_or(Workspace {
+                settings: vec![],
+                tree: vec![],
+                library: default_library(),
+                repaired: false,
+                recovered: false,
+            });
+        sanitize_workspace_asset_references(&mut workspace);
+
+        let Some(entry) = find_story_entry_mut(&mut manifest, &story.id) else {
+            let folder_name =
+                free_story_folder_name(state, root, &manifest, &story.title, &story.id);
+            write_workspace(&story_db_path(state, root, &folder_name), &workspace)?;
+            manifest.stories.push(StoryManifestEntry {
+                story: story.clone(),
+                folder_name,
+                order: None,
+            });
+            report.decisions.push(MergeDecision {
+                story_id: story.id.clone(),
+                title: story.title.clone(),
+                action: MergeAction::Added,
+                merged_story_id: None,
+            });
+            continue;
+        };
+
+        let db_path = story_db_path(state, root, &entry.folder_name);
+        let local_workspace = read_workspace(&db_path)?;
+        let identical = content_hash(&entry.story) == content_hash(story)
+            && content_hash(&local_workspace) == content_hash(&workspace);
+
+        let (action, merged_story_id) = if identical {
+            (MergeAction::Unchanged, None)
+        } else if strategy == MergeStrategy::NewestWins {
+            if parse_timestamp(&story.updated_at) > parse_timestamp(&entry.story.updated_at) {
+                write_workspace(&db_path, &workspace)?;
+                entry.story = story.clone();
+                (MergeAction::Replaced, None)
+            } else {
+                (MergeAction::KeptLocal, None)
+            }
+        } else {
+            let copy = Story {
+                id: Uuid::new_v4().to_string(),
+                title: format!("{} (合并副本)", story.title),
+                ..story.clone()
+            };
+            let folder_name = free_story_folder_name(state, root, &manifest, &copy.title, &copy.id);
+            write_workspace(&story_db_path(state, root, &folder_name), &workspace)?;
+            let copy_id = copy.id.clone();
+            manifest.stories.push(StoryManifestEntry {
+                story: copy,
+                folder_name,
+                order: None,
+            });
+            (MergeAction::KeptBoth, Some(copy_id))
+        };
+
+        report.decisions.push(MergeDecision {
+            story_id: story.id.clone(),
+            title: story.title.clone(),
+            action,
+            merged_story_id,
+        });
+    }
+
+    write_manifest(state, root, &manifest)?;
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn overwrite_external_manifest_changes(
+    app: AppHandle,
+    state: State<ProjectState>,
+) -> Result<(), String> {
+    if is_read_only(&state) {
+        return Err(READ_ONLY_PROJECT.to_string());
+    }
+    let root = require_active_root(&app, &state)?;
+    record_manifest_mtime(&state, &root);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn close_project(app: AppHandle, state: State<ProjectState>) -> Result<(), String> {
+    flush_pending_settings(&state)?;
+    stop_project_watcher(&state);
+    stop_auto_backup(&state);
+    stop_lock_heartbeat(&state);
+    state.read_only.store(false, Ordering::Relaxed);
+    clear_undo_stack(&state);
+    clear_manifest_cache(&state);
+    if let Ok(mut guard) = state.project_root.lock() {
+        *guard = None;
+    }
+    if let Ok(mut guard) = state.manifest_mtime.lock() {
+        *guard = None;
+    }
+
+    let path = selection_file_path(&app)?;
+    if path.exists() {
+        fs::remove_file(path).map_err(|error| format!("清除项目选择记录失败: {error}"))?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn export_stories(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_ids: Vec<String>,
+    prune_shared_library: Option<bool>,
+) -> Result<ExportedProjectData, String> {
+    if story_ids.is_empty() {
+        return Err("请至少选择一个故事".to_string());
+    }
+
+    let root = require_active_root(&app, &state)?;
+    let mut data = load_project_data(&state, &root)?;
+    if let Some(missing) = story_ids
+        .iter()
+        .find(|id| !data.stories.iter().any(|story| &story.id == *id))
+    {
+        return Err(format!("故事不存在: {missing}"));
+    }
+
+    data.stories.retain(|story| story_ids.contains(&story.id));
+    data.workspaces
+        .retain(|story_id, _| story_ids.contains(story_id));
+    if prune_shared_library.unwrap_or(false) {
+        prune_library_to_workspaces(&mut data.shared_library, data.workspaces.values());
+    }
+
+    Ok(ExportedProjectData {
+        app: "takecopter".to_string(),
+        schema_version: CURRENT_SCHEMA_VERSION,
+        exported_at: now_rfc3339(),
+        data,
+    })
+}
+
+#[tauri::command]
+pub fn export_stories_bundle_to_local(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_ids: Vec<String>,
+    prune_shared_library: Option<bool>,
+    filename_template: Option<String>,
+    open_after: Option<bool>,
+) -> Result<String, String> {
+    let root = require_active_root(&app, &state)?;
+    let export_dir = exports_root(&state, &root);
+    let payload = export_stories(app, state, story_ids, prune_shared_library)?;
+    fs::create_dir_all(&export_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
+    let file_path = export_dir.join(render_export_file_name(
+        filename_template.as_deref(),
+        &project_display_name(&root),
+        &export_dir,
+        ".json",
+        format!(
+            "takecopter-stories-{}.json",
+            Utc::now().format("%Y%m%d-%H%M%S")
+        ),
+    ));
+    let raw = serde_json::to_vec_pretty(&payload).map_err(|error| error.to_string())?;
+    write_file_atomically(&file_path, &raw)
+        .map_err(|error| format!("写入导出文件失败: {error}"))?;
+    if open_after.unwrap_or(true) {
+        open_path_in_file_manager(&export_dir)?;
+    }
+    Ok(file_path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn export_shared_library(app: AppHandle, state: State<ProjectState>) -> Result<String, String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let payload = ExportedLibraryData {
+        app: "takecopter".to_string(),
+        schema_version: CURRENT_SCHEMA_VERSION,
+        exported_at: now_rfc3339(),
+        library: manifest.shared_library,
+    };
+
+    let export_dir = exports_root(&state, &root);
+    fs::create_dir_all(&export_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
+    let file_path = export_dir.join(format!(
+        "takecopter-library-{}.json",
+        Utc::now().format("%Y%m%d-%H%M%S")
+    ));
+    let raw = serde_json::to_vec_pretty(&payload).map_err(|error| error.to_string())?;
+    write_file_atomically(&file_path, &raw)
+        .map_err(|error| format!("写入导出文件失败: {error}"))?;
+    Ok(file_path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn import_shared_library(
+    app: AppHandle,
+    state: State<ProjectState>,
+    payload: ExportedLibraryData,
+    mode: Option<LibraryImportMode>,
+) -> Result<SettingLibrary, String> {
+    if payload.app != "takecopter" {
+        return Err("无效的设定库文件来源".to_string());
+    }
+    if payload.schema_version > CURRENT_SCHEMA_VERSION {
+        return Err("设定库版本过新，请升级应用后再导入".to_string());
+    }
+    validate_library(&payload.library)?;
+
+    let root = require_writable_root(&app, &state)?;
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    match mode.unwrap_or_default() {
+        LibraryImportMode::Replace => manifest.shared_library = payload.library,
+        LibraryImportMode::Merge => {
+            let mut incoming = payload.library;
+            for template in &mut incoming.templates {
+                template.id = Uuid::new_v4().to_string();
+            }
+            merge_library(&mut manifest.shared_library, &incoming);
+        }
+    }
+    write_manifest(&state, &root, &manifest)?;
+    Ok(manifest.shared_library)
+}
+
+#[tauri::command]
+pub fn reorder_stories(
+    app: AppHandle,
+    state: State<ProjectState>,
+    ordered_ids: Vec<String>,
+) -> Result<(), String> {
+    let root = require_writable_root(&app, &state)?;
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    if let Some(missing) = ordered_ids
+        .iter()
+        .find(|id| find_story_entry(&manifest, id).is_none())
+    {
+        return Err(format!("故事不存在: {missing}"));
+    }
+
+    for entry in &mut manifest.stories {
+        entry.order = ordered_ids
+            .iter()
+            .position(|id| id == &entry.story.id)
+            .map(|index| index as i64);
+    }
+    write_manifest(&state, &root, &manifest)
+}
+
+#[tauri::command]
+pub fn toggle_story_pin(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<Story, String> {
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    entry.story.pinned = !entry.story.pinned;
+    let story = entry.story.clone();
+    write_manifest(&state, &root, &manifest)?;
+    Ok(story)
+}
+
+fn set_story_archived(
+    app: &AppHandle,
+    state: &ProjectState,
+    story_id: &str,
+    archived: bool,
+) -> Result<Story, String> {
+    let root = require_writable_root(app, state)?;
+    let story_lock = story_write_lock(state, story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(state);
+    let mut manifest = read_manifest(state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    entry.story.archived = archived;
+    let story = entry.story.clone();
+    write_manifest(state, &root, &manifest)?;
+    Ok(story)
+}
+
+#[tauri::command]
+pub fn archive_story(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<Story, String> {
+    set_story_archived(&app, &state, &story_id, true)
+}
+
+#[tauri::command]
+pub fn unarchive_story(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<Story, String> {
+    set_story_archived(&app, &state, &story_id, false)
+}
+
+#[tauri::command]
+pub fn duplicate_template(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: Option<String>,
+    template_id: String,
+) -> Result<SettingTemplate, String> {
+    let root = require_writable_root(&app, &state)?;
+    update_library_in_place(&state, &root, story_id.as_deref(), |library| {
+        let Some(source) = library
+            .templates
+            .iter()
+            .find(|template| template.id == template_id)
+        else {
+            return Err("模版不存在".to_string());
+        };
+        let copy = SettingTemplate {
+            id: Uuid::new_v4().to_string(),
+            name: format!("{} (副本)", source.name),
+            preset: source.preset.clone(),
+        };
+        library.templates.push(copy.clone());
+        Ok(copy)
+    })
+}
+
+#[tauri::command]
+pub fn recategorize_settings(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    node_ids: Vec<String>,
+    category: String,
+    add_missing_category: Option<bool>,
+) -> Result<usize, String> {
+    let category = category.trim().to_string();
+    if category.is_empty() {
+        return Err("分类名称不能为空".to_string());
+    }
+
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let db_path = story_db_path(&state, &root, &entry.folder_name);
+    let mut workspace = read_workspace(&db_path)?;
+    let changed = recategorize_nodes(
+        &mut workspace,
+        &node_ids,
+        &category,
+        add_missing_category.unwrap_or(false),
+    )?;
+
+    write_workspace(&db_path, &workspace)?;
+    entry.story.updated_at = now_rfc3339();
+    write_manifest(&state, &root, &manifest)?;
+    Ok(changed)
+}
+
+fn recategorize_nodes(
+    workspace: &mut Workspace,
+    node_ids: &[String],
+    category: &str,
+    add_missing_category: bool,
+) -> Result<usize, String> {
+    let key = category_key(category);
+    let category = match workspace
+        .library
+        .categories
+        .iter()
+        .find(|existing| category_key(existing) == key)
+    {
+        Some(existing) => existing.clone(),
+        None if add_missing_category => {
+            workspace.library.categories.push(category.to_string());
+            category.to_string()
+        }
+        None => return Err(format!("分类不存在: {category}")),
+    };
+
+    let mut changed = 0;
+    for node in &mut workspace.settings {
+        let matches = node_id(node).is_some_and(|id| node_ids.iter().any(|item| item == id));
+        if !matches || node.get("category").and_then(|value| value.as_str()) == Some(&category) {
+            continue;
+        }
+        if let Some(map) = node.as_object_mut() {
+            map.insert(
+                "category".to_string(),
+                serde_json::Value::String(category.clone()),
+            );
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+#[tauri::command]
+pub fn move_tree_node(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    node_id: String,
+    new_parent_id: Option<String>,
+    index: usize,
+) -> Result<Vec<serde_json::Value>, String> {
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let db_path = story_db_path(&state, &root, &entry.folder_name);
+    let mut workspace = read_workspace(&db_path)?;
+    let Some(node) = find_tree_node(&workspace.tree, &node_id) else {
+        return Err("节点不存在".to_string());
+    };
+    if let Some(parent_id) = new_parent_id.as_deref() {
+        if parent_id == node_id || find_tree_node(tree_children(node), parent_id).is_some() {
+            return Err("不能将节点移动到自身或其子节点下".to_string());
+        }
+        if find_tree_node(&workspace.tree, parent_id).is_none() {
+            return Err("目标父节点不存在".to_string());
+        }
+    }
+
+    let Some(node) = detach_tree_node(&mut workspace.tree, &node_id) else {
+        return Err("节点不存在".to_string());
+    };
+    let siblings = match new_parent_id.as_deref() {
+        None => &mut workspace.tree,
+        Some(parent_id) => {
+            let Some(serde_json::Value::Object(parent)) =
+                find_tree_node_mut(&mut workspace.tree, parent_id)
+            else {
+                return Err("目标父节点不存在".to_string());
+            };
+            let children = parent
+                .entry("children")
+                .or_insert_with(|| serde_json::Value::Array(vec![]));
+            if !children.is_array() {
+                *children = serde_json::Value::Array(vec![]);
+            }
+            children.as_array_mut().expect("children is an array")
+        }
+    };
+    siblings.insert(index.min(siblings.len()), node);
+
+    write_workspace(&db_path, &workspace)?;
+    entry.story.updated_at = now_rfc3339();
+    write_manifest(&state, &root, &manifest)?;
+    Ok(workspace.tree)
+}
+
+#[tauri::command]
+pub fn get_recent_activity(
+    app: AppHandle,
+    state: State<ProjectState>,
+    limit: Option<usize>,
+) -> Result<Vec<ActivityEntry>, String> {
+    let root = require_active_root(&app, &state)?;
+    let limit = limit.unwrap_or(50);
+    let mut entries = read_activity_entries(&activity_log_path(&root));
+    if entries.len() < limit {
+        let mut older = read_activity_entries(&rotated_activity_log_path(&root));
+        older.append(&mut entries);
+        entries = older;
+    }
+    Ok(entries.into_iter().rev().take(limit).collect())
+}
+
+#[tauri::command]
+pub fn project_size(app: AppHandle, state: State<ProjectState>) -> Result<ProjectSize, String> {
+    let root = require_active_root(&app, &state)?;
+    compute_project_size(&state, &root)
+}
+
+#[tauri::command]
+pub fn open_exports_folder(app: AppHandle, state: State<ProjectState>) -> Result<(), String> {
+    let root = require_active_root(&app, &state)?;
+    let export_dir = exports_root(&state, &root);
+    fs::create_dir_all(&export_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
+    open_path_in_file_manager(&export_dir)
+}
+
+#[tauri::command]
+pub fn open_project_root_folder(app: AppHandle, state: State<ProjectState>) -> Result<(), String> {
+    let root = require_active_root(&app, &state)?;
+    open_path_in_file_manager(&root)
+}
+
+#[tauri::command]
+pub fn schedule_settings_write(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    settings: Vec<serde_json::Value>,
+) -> Result<(), String> {
+    validate_settings(&settings)?;
+    let root = require_writable_root(&app, &state)?;
+    let delay = Duration::from_millis(project_config(&state, &root)?.settings_write_debounce_ms);
+    {
+        let mut pending = state
+            .pending_settings
+            .lock()
+            .map_err(|_| "待写入队列不可用".to_string())?;
+        pending.insert(
+            story_id,
+            PendingSettingsWrite {
+                root,
+                settings,
+                due_at: Instant::now() + delay,
+            },
+        );
+    }
+    state.settings_wakeup.notify_one();
+    start_settings_worker(&app, &state);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn flush_pending_writes(state: State<ProjectState>) -> Result<(), String> {
+    flush_pending_settings(&state)
+}
+
+#[tauri::command]
+pub fn snapshot_story(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    label: Option<String>,
+) -> Result<StorySnapshot, String> {
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let snapshot = create_story_snapshot(&state, &root, &entry.folder_name, label)?;
+    prune_story_snapshots(&state, &root, &entry.folder_name)?;
+    let _ = append_activity(&root, &story_id, "snapshot_story");
+    Ok(snapshot)
+}
+
+#[tauri::command]
+pub fn list_snapshots(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<Vec<StorySnapshot>, String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    list_story_snapshots(&state, &root, &entry.folder_name)
+}
+
+#[tauri::command]
+pub fn restore_snapshot(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    timestamp: String,
+) -> Result<Workspace, String> {
+    if !is_valid_snapshot_timestamp(&timestamp) {
+        return Err("快照不存在".to_string());
+    }
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Ok(mut pending) = state.pending_settings.lock() {
+        pending.remove(&story_id);
+    }
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let source =
+        story_history_dir(&state, &root, &entry.folder_name).join(format!("{timestamp}.db"));
+    if !source.is_file() {
+        return Err("快照不存在".to_string());
+    }
+
+    create_story_snapshot(
+        &state,
+        &root,
+        &entry.folder_name,
+        Some("恢复前自动快照".to_string()),
+    )?;
+    let db_path = story_db_path(&state, &root, &entry.folder_name);
+    fs::copy(&source, &db_path).map_err(|error| format!("恢复快照失败: {error}"))?;
+    prune_story_snapshots(&state, &root, &entry.folder_name)?;
+    let workspace = read_workspace(&db_path)?;
+
+    entry.story.updated_at = now_rfc3339();
+    write_manifest(&state, &root, &manifest)?;
+    let _ = append_activity(&root, &story_id, "restore_snapshot");
+    Ok(workspace)
+}
+
+#[tauri::command]
+pub fn undo_last(app: AppHandle, state: State<ProjectState>) -> Result<UndoResult, String> {
+    let root = require_writable_root(&app, &state)?;
+    let entry = state
+        .undo_stack
+        .lock()
+        .ok()
+        .and_then(|mut stack| stack.pop())
+        .ok_or_else(|| "没有可撤销的操作".to_string())?;
+    apply_undo(&state, &root, entry)
+}
+
+#[tauri::command]
+pub fn batch_update_workspaces(
+    app: AppHandle,
+    state: State<ProjectState>,
+    updates: Vec<WorkspaceUpdate>,
+) -> Result<Vec<WorkspaceUpdateResult>, String> {
+    let root = require_writable_root(&app, &state)?;
+    let story_locks =
+        story_write_locks(&state, updates.iter().map(|update| update.story_id.clone()));
+    let _story_guards = story_locks
+        .iter()
+        .map(|lock| lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+        .collect::<Vec<_>>();
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let mut results = Vec::with_capacity(updates.len());
+    let mut touched = Vec::new();
+
+    for update in updates {
+        let story_id = update.story_id.clone();
+        let outcome = apply_workspace_update(&state, &root, &mut manifest, update);
+
+        match outcome {
+            Ok(()) => {
+                touched.push(story_id.clone());
+                results.push(WorkspaceUpdateResult {
+                    story_id,
+                    ok: true,
+                    error: None,
+                });
+            }
+            Err(error) => results.push(WorkspaceUpdateResult {
+                story_id,
+                ok: false,
+                error: Some(error),
+            }),
+        }
+    }
+
+    if !touched.is_empty() {
+        write_manifest(&state, &root, &manifest)?;
+        for story_id in &touched {
+            let _ = append_activity(&root, story_id, "batch_update_workspaces");
+        }
+    }
+    Ok(results)
+}
+
+#[tauri::command]
+pub fn init_git_repo(app: AppHandle, state: State<ProjectState>) -> Result<(), String> {
+    let root = require_writable_root(&app, &state)?;
+    if !root.join(".git").exists() {
+        let output = run_git(&root, &["init", "-q"])?;
+        if !output.status.success() {
+            return Err(format!(
+                "初始化 Git 仓库失败: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+    }
+
+    let gitignore_path = root.join(".gitignore");
+    let existing = fs::read_to_string(&gitignore_path).unwrap_or_default();
+    let exports = format!("{}/", exports_folder_name(&project_config(&state, &root)?));
+    let missing = [exports.as_str(), ".lock", ".session.json", ".trash/"]
+        .into_iter()
+        .filter(|pattern| !existing.lines().any(|line| line.trim() == *pattern))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        let mut content = existing;
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        for pattern in missing {
+            content.push_str(pattern);
+            content.push('\n');
+        }
+        fs::write(&gitignore_path, content)
+            .map_err(|error| format!("写入 .gitignore 失败: {error}"))?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn rename_project_root(
+    app: AppHandle,
+    state: State<ProjectState>,
+    new_name: String,
+) -> Result<String, String> {
+    let name = new_name.trim();
+    if !is_valid_folder_name(name) {
+        return Err("项目目录名称无效".to_string());
+    }
+
+    let root = require_writable_root(&app, &state)?;
+    let Some(parent) = root.parent() else {
+        return Err("无法重命名项目目录".to_string());
+    };
+    let target = parent.join(name);
+    if target == root {
+        return Ok(root.to_string_lossy().to_string());
+    }
+    if target.exists() {
+        return Err("目标目录已存在，请使用其他名称".to_string());
+    }
+    ensure_lock_not_foreign(&root)?;
+
+    flush_pending_settings(&state)?;
+    stop_project_watcher(&state);
+    if let Err(error) = fs::rename(&root, &target) {
+        start_project_watcher(&app, &state, &root)?;
+        return Err(format!("重命名项目目录失败: {error}"));
+    }
+
+    set_active_root(&app, &state, &target)?;
+    Ok(target.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn move_project_root(
+    app: AppHandle,
+    state: State<ProjectState>,
+    dest_parent_dir: String,
+    exclude_exports: Option<bool>,
+    remove_original: Option<bool>,
+) -> Result<String, String> {
+    let root = require_writable_root(&app, &state)?;
+    let parent = PathBuf::from(dest_parent_dir.trim());
+    if !parent.is_dir() {
+        return Err("目标位置不存在".to_string());
+    }
+    let Some(name) = root.file_name() else {
+        return Err("无法移动项目目录".to_string());
+    };
+    let target = parent.join(name);
+    let canonical_root =
+        fs::canonicalize(&root).map_err(|error| format!("读取目录失败: {error}"))?;
+    let canonical_parent =
+        fs::canonicalize(&parent).map_err(|error| format!("读取目录失败: {error}"))?;
+    if canonical_parent.starts_with(&canonical_root) {
+        return Err("目标位置不能位于项目目录内".to_string());
+    }
+    if project_manifest_path(&target).exists() {
+        return Err("目标位置已包含项目，请选择其他位置".to_string());
+    }
+    ensure_lock_not_foreign(&root)?;
+    flush_pending_settings(&state)?;
+
+    let mut excluded = vec![".lock".to_string()];
+    if exclude_exports.unwrap_or(false) {
+        excluded.push(exports_folder_name(&project_config(&state, &root)?));
+    }
+    let excluded = excluded.iter().map(String::as_str).collect::<Vec<_>>();
+    ensure_free_space(&parent, dir_size(&root, &excluded))?;
+
+    let created_target = !target.exists();
+    let copied = copy_project_tree(&app, &state, &root, &target, &excluded)
+        .and_then(|_| verify_project_copy(&state, &root, &target));
+    if let Err(error) = copied {
+        if created_target {
+            let _ = fs::remove_dir_all(&target);
+        }
+        return Err(error);
+    }
+
+    stop_project_watcher(&state);
+    set_active_root(&app, &state, &target)?;
+    ensure_root_layout(&state, &target)?;
+    if remove_original.unwrap_or(false) {
+        if let Err(error) = fs::remove_dir_all(&root) {
+            log::error!("删除原项目目录失败: {error}");
+        }
+    }
+    Ok(target.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn list_stories(
+    app: AppHandle,
+    state: State<ProjectState>,
+    include_archived: Option<bool>,
+) -> Result<Vec<Story>, String> {
+    let root = require_active_root(&app, &state)?;
+    let mut manifest = read_manifest(&state, &root)?;
+    sort_manifest_stories(&mut manifest.stories);
+    Ok(manifest
+        .stories
+        .into_iter()
+        .map(|entry| entry.story)
+        .filter(|story| include_archived.unwrap_or(false) || !story.archived)
+        .collect())
+}
+
+#[tauri::command]
+pub fn take_pending_import(state: State<ProjectState>) -> Option<ExportPreview> {
+    state
+        .pending_import
+        .lock()
+        .ok()
+        .and_then(|mut pending| pending.take())
+}
+
+#[tauri::command]
+pub fn validate_export_file(path: String) -> ExportValidation {
+    let path = PathBuf::from(path.trim());
+    if fs::read(&path).is_ok_and(|raw| raw.starts_with(ENCRYPTED_EXPORT_MAGIC)) {
+        return ExportValidation {
+            kind: Some("encrypted".to_string()),
+            error: Some("加密导出文件需要输入密码后才能校验".to_string()),
+            ..ExportValidation::default()
+        };
+    }
+
+    match preview_export_file(&path) {
+        Ok(preview) => {
+            let error = (preview.schema_version > CURRENT_SCHEMA_VERSION)
+                .then(|| "导出文件版本过新，请升级应用后再导入".to_string());
+            ExportValidation {
+                valid: error.is_none(),
+                kind: Some(preview.kind),
+                schema_version: Some(preview.schema_version),
+                story_titles: preview.story_titles,
+                error,
+            }
+        }
+        Err(error) => ExportValidation {
+            error: Some(error),
+            ..ExportValidation::default()
+        },
+    }
+}
+
+#[tauri::command]
+pub fn backup_local_database_incremental(
+    app: AppHandle,
+    state: State<ProjectState>,
+    open_after: Option<bool>,
+) -> Result<BackupReport, String> {
+    let root = require_active_root(&app, &state)?;
+    let export_dir = resolve_backup_parent(&state, &root, None)?;
+    ensure_free_space(
+        &export_dir,
+        compute_project_size(&state, &root)?.total_bytes,
+    )?;
+    let backup_dir = new_backup_dir(&export_dir);
+    let report = incremental_backup(&state, &root, &backup_dir)?;
+    if open_after.unwrap_or(true) {
+        open_path_in_file_manager(&backup_dir)?;
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn get_auto_backup_status(state: State<ProjectState>) -> AutoBackupStatus {
+    state
+        .auto_backup
+        .lock()
+        .ok()
+        .and_then(|guard| {
+            guard
+                .as_ref()
+                .and_then(|timer| timer.status.lock().ok().map(|status| status.clone()))
+        })
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn search_stories(
+    app: AppHandle,
+    state: State<ProjectState>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<Story>, String> {
+    let root = require_active_root(&app, &state)?;
+    let query = query.trim().to_lowercase().chars().collect::<Vec<_>>();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let manifest = read_manifest(&state, &root)?;
+    let mut scored = manifest
+        .stories
+        .into_iter()
+        .filter_map(|entry| {
+            let title_score = fuzzy_score(&query, &entry.story.title).map(|score| score * 2);
+            let description_score = fuzzy_score(&query, &entry.story.description);
+            title_score
+                .max(description_score)
+                .map(|score| (score, entry.story))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| compare_updated_desc(&a.1, &b.1)));
+    Ok(scored
+        .into_iter()
+        .take(limit.unwrap_or(20))
+        .map(|(_, story)| story)
+        .collect())
+}
+
+#[tauri::command]
+pub fn search_project_ranked(
+    app: AppHandle,
+    state: State<ProjectState>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<SearchHit>, String> {
+    let query = query.trim().to_string();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let limit = limit.unwrap_or(50);
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+
+    let mut hits = Vec::new();
+    for entry in &manifest.stories {
+        let db_path = story_db_path(&state, &root, &entry.folder_name);
+        if !db_path.exists() {
+            continue;
+        }
+        for (node_id, kind, title, body, rank) in
+            search_story_index(&db_path, &query, limit, is_read_only(&state))?
+        {
+            hits.push(SearchHit {
+                story_id: entry.story.id.clone(),
+                story_title: entry.story.title.clone(),
+                snippet: search_snippet(&title, &body, &query),
+                node_id,
+                kind,
+                title,
+                rank,
+            });
+        }
+    }
+
+    hits.sort_by(|a, b| a.rank.total_cmp(&b.rank));
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+#[tauri::command]
+pub fn tag_usage(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: Option<String>,
+) -> Result<TagUsage, String> {
+    let root = require_active_root(&app, &state)?;
+    library_tag_usage(&state, &root, story_id.as_deref())
+}
+
+#[tauri::command]
+pub fn remove_unused_tags(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: Option<String>,
+) -> Result<Vec<String>, String> {
+    let root = require_writable_root(&app, &state)?;
+    let usage = library_tag_usage(&state, &root, story_id.as_deref())?;
+    if usage.unused.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let unused = usage
+        .unused
+        .iter()
+        .map(|name| name.trim().to_lowercase())
+        .collect::<HashSet<_>>();
+    let removed = update_library_in_place(&state, &root, story_id.as_deref(), |library| {
+        let mut removed = Vec::new();
+        library.tags.retain(|tag| {
+            let keep = !unused.contains(&tag.name.trim().to_lowercase());
+            if !keep {
+                removed.push(tag.name.clone());
+            }
+            keep
+        });
+        Ok(removed)
+    })?;
+    if let Some(story_id) = &story_id {
+        let _ = append_activity(&root, story_id, "remove_unused_tags");
+    }
+    Ok(removed)
+}
+
+#[tauri::command]
+pub fn reorder_categories(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: Option<String>,
+    ordered: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let root = require_writable_root(&app, &state)?;
+    update_library_in_place(&state, &root, story_id.as_deref(), |library| {
+        let ordered = ordered
+            .iter()
+            .map(|category| category.trim().to_string())
+            .collect::<Vec<_>>();
+        let requested = ordered.iter().collect::<HashSet<_>>();
+        let existing = library.categories.iter().collect::<HashSet<_>>();
+        if requested.len() != ordered.len()
+            || ordered.len() != library.categories.len()
+            || requested != existing
+        {
+            return Err("分类排序必须包含且仅包含现有的全部分类".to_string());
+        }
+        library.categories = ordered;
+        Ok(library.categories.clone())
+    })
+}
+
+#[tauri::command]
+pub fn rename_category(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: Option<String>,
+    old: String,
+    new: String,
+    merge: Option<bool>,
+) -> Result<usize, String> {
+    let old = old.trim().to_string();
+    let new = new.trim().to_string();
+    if new.is_empty() {
+        return Err("分类名称不能为空".to_string());
+    }
+    if old == new {
+        return Ok(0);
+    }
+    let merge = merge.unwrap_or(false);
+
+    let root = require_writable_root(&app, &state)?;
+    if let Some(story_id) = story_id {
+        let story_lock = story_write_lock(&state, &story_id);
+        let _story_guard = story_lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let _manifest_guard = manifest_write_lock(&state);
+        let mut manifest = read_manifest(&state, &root)?;
+        let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+            return Err("故事不存在".to_string());
+        };
+        let db_path = story_db_path(&state, &root, &entry.folder_name);
+        let mut workspace = read_workspace(&db_path)?;
+        rename_library_category(&mut workspace.library, &old, &new, merge)?;
+        let changed = rename_node_categories(&mut workspace.settings, &old, &new);
+        write_workspace(&db_path, &workspace)?;
+        entry.story.updated_at = now_rfc3339();
+        write_manifest(&state, &root, &manifest)?;
+        let _ = append_activity(&root, &story_id, "rename_category");
+        return Ok(changed);
+    }
+
+    let story_locks = story_write_locks(
+        &state,
+        read_manifest(&state, &root)?
+            .stories
+            .into_iter()
+            .map(|entry| entry.story.id),
+    );
+    let _story_guards = story_locks
+        .iter()
+        .map(|lock| lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+        .collect::<Vec<_>>();
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    rename_library_category(&mut manifest.shared_library, &old, &new, merge)?;
+    let mut changed = 0;
+    for entry in &mut manifest.stories {
+        let db_path = story_db_path(&state, &root, &entry.folder_name);
+        let mut workspace = read_workspace(&db_path)?;
+        let story_changed = rename_node_categories(&mut workspace.settings, &old, &new);
+        if story_changed > 0 {
+            write_workspace(&db_path, &workspace)?;
+            entry.story.updated_at = now_rfc3339();
+            changed += story_changed;
+        }
+    }
+    write_manifest(&state, &root, &manifest)?;
+    Ok(changed)
+}
+
+#[tauri::command]
+pub fn get_story_library(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<SettingLibrary, String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    read_library_from_db(
+        &story_db_path(&state, &root, &entry.folder_name),
+        is_read_only(&state),
+    )
+}
+
+#[tauri::command]
+pub fn get_shared_library(
+    app: AppHandle,
+    state: State<ProjectState>,
+) -> Result<SettingLibrary, String> {
+    let root = require_active_root(&app, &state)?;
+    Ok(read_manifest(&state, &root)?.shared_library)
+}
+
+#[tauri::command]
+pub fn apply_template(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    template_id: String,
+    values: HashMap<String, String>,
+    append: Option<bool>,
+) -> Result<AppliedTemplate, String> {
+    let root = if append.unwrap_or(false) {
+        require_writable_root(&app, &state)?
+    } else {
+        require_active_root(&app, &state)?
+    };
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let shared_library = manifest.shared_library.clone();
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let db_path = story_db_path(&state, &root, &entry.folder_name);
+    let mut workspace = read_workspace(&db_path)?;
+    let Some(template) = workspace
+        .library
+        .templates
+        .iter()
+        .chain(shared_library.templates.iter())
+        .find(|template| template.id == template_id)
+    else {
+        return Err("模版不存在".to_string());
+    };
+    let (node, used_keys) = template_node(template, &values, workspace.settings.len() + 1)?;
+
+    let appended = append.unwrap_or(false);
+    if appended {
+        workspace.settings.push(node.clone());
+        write_workspace(&db_path, &workspace)?;
+        entry.story.updated_at = now_rfc3339();
+        write_manifest(&state, &root, &manifest)?;
+        let _ = append_activity(&root, &story_id, "apply_template");
+    }
+
+    Ok(AppliedTemplate {
+        node,
+        used_keys,
+        appended,
+    })
+}
+
+#[tauri::command]
+pub fn migrate_legacy_folders(
+    app: AppHandle,
+    state: State<ProjectState>,
+) -> Result<MigrationReport, String> {
+    let root = require_writable_root(&app, &state)?;
+    flush_pending_settings(&state)?;
+    let story_locks = story_write_locks(
+        &state,
+        read_manifest(&state, &root)?
+            .stories
+            .into_iter()
+            .map(|entry| entry.story.id),
+    );
+    let _story_guards = story_locks
+        .iter()
+        .map(|lock| lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+        .collect::<Vec<_>>();
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+
+    let report = migrate_legacy_story_folders(&state, &root, &mut manifest)?;
+    write_manifest(&state, &root, &manifest)?;
+    for moved in &report.moved {
+        let _ = append_activity(&root, &moved.story_id, "migrate_legacy_folder");
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn repair_duplicate_folders(
+    app: AppHandle,
+    state: State<ProjectState>,
+) -> Result<Vec<FolderRepair>, String> {
+    let root = require_writable_root(&app, &state)?;
+    flush_pending_settings(&state)?;
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let repairs = repair_folder_name_collisions(&state, &root, &mut manifest)?;
+    if repairs.is_empty() {
+        return Ok(repairs);
+    }
+
+    write_manifest(&state, &root, &manifest)?;
+    for repair in &repairs {
+        let _ = append_activity(&root, &repair.story_id, "repair_duplicate_folder");
+    }
+    Ok(repairs)
+}
+
+#[tauri::command]
+pub fn current_project_root(
+    app: AppHandle,
+    state: State<ProjectState>,
+) -> Result<Option<String>, String> {
+    Ok(resolve_state_root(&app, &state)?.map(|root| root.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+pub fn open_project_root_readonly(
+    app: AppHandle,
+    state: State<ProjectState>,
+    root_path: String,
+) -> Result<(), String> {
+    let target = PathBuf::from(root_path.trim());
+    if !target.exists() {
+        return Err("项目目录不存在".to_string());
+    }
+    if !project_manifest_path(&target).exists() {
+        return Err("未找到 project.json，请先创建项目目录或选择有效项目目录".to_string());
+    }
+
+    flush_pending_settings(&state)?;
+    let _ = read_manifest_with_warning(&target)?;
+    activate_root(&app, &state, &target, true)
+}
+
+#[tauri::command]
+pub fn get_cover_palette(
+    app: AppHandle,
+    state: State<ProjectState>,
+) -> Result<Vec<String>, String> {
+    let root = require_active_root(&app, &state)?;
+    Ok(cover_palette(&state, &root))
+}
+
+#[tauri::command]
+pub fn update_story_cover(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    cover_color: String,
+) -> Result<Story, String> {
+    let root = require_writable_root(&app, &state)?;
+    let cover_color = validate_cover_color(&state, &root, &cover_color)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    entry.story.cover_color = cover_color;
+    entry.story.updated_at = now_rfc3339();
+    let story = entry.story.clone();
+    write_manifest(&state, &root, &manifest)?;
+    let _ = append_activity(&root, &story_id, "update_story_cover");
+    Ok(story)
+}
+
+#[tauri::command]
+pub fn import_asset(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    source_path: String,
+) -> Result<ImportedAsset, String> {
+    let root = require_writable_root(&app, &state)?;
+    let config = project_config(&state, &root)?;
+    let source = PathBuf::from(source_path.trim());
+    let extension = source
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let folder = if extension_allowed(&config.allowed_image_extensions, &extension) {
+        "images"
+    } else if extension_allowed(&config.allowed_video_extensions, &extension) {
+        "videos"
+    } else {
+        return Err(format!(
+            "不支持的素材格式: {extension}（允许的格式由 allowedImageExtensions/allowedVideoExtensions 配置）"
+        ));
+    };
+    let Some(file_name) = source
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+    else {
+        return Err("素材文件路径无效".to_string());
+    };
+    let size = fs::metadata(&source)
+        .map_err(|error| format!("读取素材文件失败: {error}"))?
+        .len();
+    if config.max_asset_bytes > 0 && size > config.max_asset_bytes {
+        return Err(format!(
+            "素材文件过大: {size} 字节，超过 maxAssetBytes 上限 {} 字节",
+            config.max_asset_bytes
+        ));
+    }
+    let bytes = fs::read(&source).map_err(|error| format!("读取素材文件失败: {error}"))?;
+
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    let story_dir = story_root(&state, &root, &entry.folder_name);
+    let (reference, deduplicated_bytes) =
+        store_asset_bytes(&story_dir, &format!("assets/{folder}/{file_name}"), &bytes)?;
+    let thumbnail_path = match thumbnail_reference(&reference) {
+        Some(thumbnail) if story_dir.join(&thumbnail).exists() => Some(thumbnail),
+        _ => generate_thumbnail(&story_dir, &reference),
+    };
+    Ok(ImportedAsset {
+        reference,
+        size_bytes: bytes.len() as u64,
+        deduplicated_bytes,
+        thumbnail_path,
+    })
+}
+
+#[tauri::command]
+pub fn regenerate_thumbnails(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<usize, String> {
+    let root = require_writable_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let story_dir = story_root(&state, &root, &entry.folder_name);
+    let thumbs_dir = story_dir.join("assets").join("images").join(".thumbs");
+    if thumbs_dir.exists() {
+        fs::remove_dir_all(&thumbs_dir).map_err(|error| format!("清理缩略图失败: {error}"))?;
+    }
+    Ok(story_asset_files(&story_dir)
+        .iter()
+        .filter_map(|(reference, _)| generate_thumbnail(&story_dir, reference))
+        .count())
+}
+
+#[tauri::command]
+pub fn list_assets(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<AssetInventory, String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    let workspace =
+        read_story_workspace(&state, &story_db_path(&state, &root, &entry.folder_name))?;
+    Ok(story_asset_inventory(
+        &story_root(&state, &root, &entry.folder_name),
+        &workspace,
+    ))
+}
+
+#[tauri::command]
+pub fn open_asset(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    relative_path: String,
+) -> Result<(), String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
     let Some(entry) = find_story_entry(&manifest, &story_id) else {
         return Err("故事不存在".to_string());
     };
-    open_path_in_file_manager(&story_root(&root, &entry.folder_name))
+    let story_dir = story_root(&state, &root, &entry.folder_name);
+    let trimmed = relative_path.trim().trim_start_matches("./");
+    let reference = if trimmed.starts_with("assets/") {
+        trimmed.to_string()
+    } else {
+        format!("assets/{trimmed}")
+    };
+    let Some(path) = story_asset_path(&story_dir, &reference) else {
+        return Err("素材路径不合法".to_string());
+    };
+    let resolved = path
+        .canonicalize()
+        .map_err(|_| format!("素材不存在: {reference}"))?;
+    let assets_dir = story_dir
+        .join("assets")
+        .canonicalize()
+        .map_err(|error| format!("读取素材目录失败: {error}"))?;
+    if !resolved.starts_with(&assets_dir) || !resolved.is_file() {
+        return Err("素材路径不合法".to_string());
+    }
+    open_path_in_file_manager(&resolved)
 }
 
 #[tauri::command]
-pub fn open_story_database(
+pub fn export_story_to_folder(
     app: AppHandle,
     state: State<ProjectState>,
     story_id: String,
+    dest_dir: String,
+) -> Result<String, String> {
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry(&manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+    let story_dir = story_root(&state, &root, &entry.folder_name);
+    let payload = export_story(app, state, story_id, None)?;
+    let dest_dir = PathBuf::from(dest_dir.trim());
+    write_story_folder(&dest_dir, &story_dir, &payload)?;
+    Ok(dest_dir.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn import_story_from_folder(
+    app: AppHandle,
+    state: State<ProjectState>,
+    src_dir: String,
+) -> Result<ImportResult, String> {
+    let payload = read_story_folder(Path::new(src_dir.trim()))?;
+    import_story(app, state, payload)
+}
+
+#[tauri::command]
+pub fn export_stories_to_local(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_ids: Vec<String>,
+    filename_template: Option<String>,
+    open_after: Option<bool>,
+) -> Result<Vec<String>, String> {
+    if story_ids.is_empty() {
+        return Err("请至少选择一个故事".to_string());
+    }
+
+    let root = require_active_root(&app, &state)?;
+    let manifest = read_manifest(&state, &root)?;
+    let mut entries = Vec::new();
+    for story_id in &story_ids {
+        let Some(entry) = find_story_entry(&manifest, story_id) else {
+            return Err(format!("故事不存在: {story_id}"));
+        };
+        entries.push(entry);
+    }
+
+    let export_dir = exports_root(&state, &root);
+    fs::create_dir_all(&export_dir).map_err(|error| format!("创建导出目录失败: {error}"))?;
+    let mut written = Vec::new();
+    for entry in entries {
+        let payload = story_export_payload(&state, &root, entry, false)?;
+        let file_name = render_export_file_name(
+            filename_template.as_deref(),
+            &payload.story.title,
+            &export_dir,
+            ".json",
+            format!(
+                "takecopter-story-{}-{}.json",
+                payload.story.id,
+                Utc::now().format("%Y%m%d-%H%M%S")
+            ),
+        );
+        let mut file_path = export_dir.join(&file_name);
+        let mut suffix = 2;
+        while written.contains(&file_path) {
+            file_path = export_dir.join(format!(
+                "{}-{suffix}.json",
+                file_name.trim_end_matches(".json")
+            ));
+            suffix += 1;
+        }
+        let raw = serde_json::to_vec_pretty(&payload).map_err(|error| error.to_string())?;
+        write_file_atomically(&file_path, &raw)
+            .map_err(|error| format!("写入导出文件失败: {error}"))?;
+        written.push(file_path);
+    }
+
+    if open_after.unwrap_or(true) {
+        open_path_in_file_manager(&export_dir)?;
+    }
+    Ok(written
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect())
+}
+
+#[tauri::command]
+pub fn list_exports(app: AppHandle, state: State<ProjectState>) -> Result<Vec<ExportInfo>, String> {
+    let root = require_active_root(&app, &state)?;
+    Ok(list_export_entries(&exports_root(&state, &root)))
+}
+
+#[tauri::command]
+pub fn delete_export(
+    app: AppHandle,
+    state: State<ProjectState>,
+    name: String,
 ) -> Result<(), String> {
+    let root = require_writable_root(&app, &state)?;
+    let name = name.trim();
+    let mut components = Path::new(name).components();
+    let is_direct_child = matches!(components.next(), Some(std::path::Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\'])
+        && !name.starts_with('.');
+    if !is_direct_child {
+        return Err("导出文件名不合法".to_string());
+    }
+    let path = exports_root(&state, &root).join(name);
+    let meta = fs::symlink_metadata(&path).map_err(|_| format!("导出文件不存在: {name}"))?;
+    if meta.is_dir() {
+        fs::remove_dir_all(&path)
+    } else {
+        fs::remove_file(&path)
+    }
+    .map_err(|error| format!("删除导出文件失败: {error}"))
+}
+
+#[tauri::command]
+pub fn update_tree_node(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+    node_id: String,
+    patch: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let root = require_writable_root(&app, &state)?;
+    let story_lock = story_write_lock(&state, &story_id);
+    let _story_guard = story_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _manifest_guard = manifest_write_lock(&state);
+    let mut manifest = read_manifest(&state, &root)?;
+    let Some(entry) = find_story_entry_mut(&mut manifest, &story_id) else {
+        return Err("故事不存在".to_string());
+    };
+
+    let db_path = story_db_path(&state, &root, &entry.folder_name);
+    let mut workspace = read_workspace(&db_path)?;
+    let Some(node) = find_tree_node_mut(&mut workspace.tree, &node_id) else {
+        return Err("节点不存在".to_string());
+    };
+    merge_node_patch(node, patch)?;
+    let updated = node.clone();
+
+    write_workspace(&db_path, &workspace)?;
+    entry.story.updated_at = now_rfc3339();
+    write_manifest(&state, &root, &manifest)?;
+    let _ = append_activity(&root, &story_id, "update_tree_node");
+    Ok(updated)
+}
+
+#[tauri::command]
+pub fn tree_outline(
+    app: AppHandle,
+    state: State<ProjectState>,
+    story_id: String,
+) -> Result<Vec<OutlineEntry>, String> {
     let root = require_active_root(&app, &state)?;
-    let manifest = read_manifest(&root)?;
+    let manifest = read_manifest(&state, &root)?;
     let Some(entry) = find_story_entry(&manifest, &story_id) else {
         return Err("故事不存在".to_string());
     };
-    open_path_in_file_manager(&story_db_path(&root, &entry.folder_name))
+    let workspace =
+        read_story_workspace(&state, &story_db_path(&state, &root, &entry.folder_name))?;
+    let mut outline = Vec::new();
+    collect_outline(&workspace.tree, 0, &mut outline);
+    Ok(outline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn story(id: &str, title: &str) -> Story {
+        Story {
+            id: id.to_string(),
+            title: title.to_string(),
+            description: String::new(),
+            updated_at: now_rfc3339(),
+            cover_color: PALETTE_COLORS[0].to_string(),
+            pinned: false,
+            archived: false,
+        }
+    }
+
+    fn temp_dir(label: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("takecopter-{label}-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn imported_story_requires_uuid_id_and_title() {
+        let id = Uuid::new_v4().to_string();
+        assert!(validate_imported_story(&story(&id, "第一章")).is_ok());
+        assert!(validate_imported_story(&story("", "第一章")).is_err());
+        assert!(validate_imported_story(&story("  ", "第一章")).is_err());
+        assert!(validate_imported_story(&story("story-1", "第一章")).is_err());
+        assert!(validate_imported_story(&story(&id, "")).is_err());
+        assert!(validate_imported_story(&story(&id, "   ")).is_err());
+    }
+
+    #[test]
+    fn legacy_db_without_library_column_reads_same_default_as_new_db() {
+        let dir = temp_dir("library-default");
+        let legacy = dir.join("legacy.db");
+        Connection::open(&legacy)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE workspace (id INTEGER PRIMARY KEY, settings_json TEXT NOT NULL, tree_json TEXT NOT NULL);
+                 INSERT INTO workspace (id, settings_json, tree_json) VALUES (1, '[]', '[]');",
+            )
+            .unwrap();
+        let created = dir.join("created.db");
+        open_story_db(&created)
+            .unwrap()
+            .execute(
+                "INSERT INTO workspace (id, settings_json, tree_json) VALUES (1, '[]', '[]')",
+                [],
+            )
+            .unwrap();
+
+        let migrated = read_workspace_from_db(&legacy, false).unwrap().library;
+        let fresh = read_workspace_from_db(&created, false).unwrap().library;
+        assert_eq!(migrated.categories, default_library().categories);
+        assert_eq!(
+            serde_json::to_value(&migrated).unwrap(),
+            serde_json::to_value(&fresh).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_custom_field_kind_keeps_library_readable() {
+        let library = serde_json::from_value::<SettingLibrary>(serde_json::json!({
+            "templates": [{
+                "id": "t1",
+                "name": "角色",
+                "preset": {
+                    "type": "character",
+                    "customFields": [
+                        { "name": "生日", "value": "2024-01-01", "kind": "date" },
+                        { "name": "坐标", "value": "1,2", "kind": "geo" }
+                    ]
+                }
+            }]
+        }))
+        .unwrap();
+        let fields = &library.templates[0].preset.custom_fields;
+        assert_eq!(fields[0].kind, CustomFieldKind::Date);
+        assert_eq!(fields[1].kind, CustomFieldKind::Other("geo".to_string()));
+        assert!(validate_library(&library).is_ok());
+        assert_eq!(
+            serde_json::to_value(&fields[1]).unwrap()["kind"],
+            serde_json::json!("geo")
+        );
+    }
+
+    #[test]
+    fn external_manifest_change_is_detected_before_writes() {
+        let root = temp_dir("external-change");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        record_manifest_mtime(&state, &root);
+        assert!(ensure_manifest_unchanged(&state, &root).is_ok());
+
+        std::thread::sleep(Duration::from_millis(20));
+        let raw = fs::read_to_string(project_manifest_path(&root)).unwrap();
+        fs::write(project_manifest_path(&root), format!("{raw}\n")).unwrap();
+        assert_eq!(
+            ensure_manifest_unchanged(&state, &root).unwrap_err(),
+            MANIFEST_CHANGED_EXTERNALLY
+        );
+        let manifest = read_manifest(&state, &root).unwrap();
+        assert!(write_manifest(&state, &root, &manifest).is_err());
+
+        record_manifest_mtime(&state, &root);
+        assert!(write_manifest(&state, &root, &manifest).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_dir_skips_self_referential_symlink() {
+        let source = temp_dir("symlink-loop");
+        fs::create_dir_all(source.join("stories/a")).unwrap();
+        fs::write(source.join("stories/a/story.db"), b"db").unwrap();
+        std::os::unix::fs::symlink(&source, source.join("stories/a/loop")).unwrap();
+
+        let target = temp_dir("symlink-loop-copy").join("copy");
+        copy_dir_recursive(&source, &target, &[]).unwrap();
+        assert_eq!(fs::read(target.join("stories/a/story.db")).unwrap(), b"db");
+        assert!(!target.join("stories/a/loop").exists());
+    }
+
+    #[test]
+    fn backup_omits_runtime_state_and_exports() {
+        let root = temp_dir("backup-excludes");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        write_project_lock(&root).unwrap();
+        fs::write(project_session_path(&root), b"{}").unwrap();
+        fs::create_dir_all(root.join(".trash/old")).unwrap();
+        fs::write(
+            exports_root(&state, &root).join("takecopter-project-1.json"),
+            b"{}",
+        )
+        .unwrap();
+        fs::create_dir_all(stories_root(&state, &root).join("a")).unwrap();
+        fs::write(stories_root(&state, &root).join("a/story.db"), b"db").unwrap();
+
+        let backup = create_backup(&state, &root, None).unwrap();
+        assert!(backup.join("project.json").exists());
+        assert!(backup.join("stories/a/story.db").exists());
+        for excluded in [".lock", ".session.json", ".trash", "exports"] {
+            assert!(!backup.join(excluded).exists(), "{excluded}");
+        }
+    }
+
+    #[test]
+    fn changing_layout_folders_moves_existing_data() {
+        let root = temp_dir("layout-move");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        fs::create_dir_all(stories_root(&state, &root).join("a")).unwrap();
+        fs::write(stories_root(&state, &root).join("a/story.db"), b"db").unwrap();
+
+        let current = project_config(&state, &root).unwrap();
+        let next = ProjectConfig {
+            stories_dir: Some("novels".to_string()),
+            ..current.clone()
+        };
+        let moved = move_layout_folders(&root, &current, &next).unwrap();
+        assert_eq!(moved.len(), 1);
+        write_config(&state, &root, &next).unwrap();
+        assert_eq!(fs::read(story_db_path(&state, &root, "a")).unwrap(), b"db");
+        assert!(!root.join("stories").exists());
+
+        fs::create_dir_all(root.join("archive/keep")).unwrap();
+        let blocked = ProjectConfig {
+            stories_dir: Some("archive".to_string()),
+            ..next.clone()
+        };
+        assert!(move_layout_folders(&root, &next, &blocked).is_err());
+        assert!(root.join("novels/a/story.db").exists());
+    }
+
+    #[test]
+    fn corrupt_config_is_an_error_not_the_default_layout() {
+        let root = temp_dir("corrupt-config");
+        let state = ProjectState::default();
+        let config = ProjectConfig {
+            stories_dir: Some("novels".to_string()),
+            ..ProjectConfig::default()
+        };
+        write_config(&state, &root, &config).unwrap();
+        assert_eq!(stories_root(&state, &root), root.join("novels"));
+
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(project_config_path(&root), b"{not json").unwrap();
+        assert!(project_config(&state, &root).is_err());
+        assert!(ensure_root_layout(&state, &root).is_err());
+        assert_eq!(stories_root(&state, &root), root.join("novels"));
+        assert!(!root.join("stories").exists());
+    }
+
+    #[test]
+    fn concurrent_manifest_writers_do_not_lose_updates() {
+        let root = temp_dir("manifest-hammer");
+        let state = Arc::new(ProjectState::default());
+        ensure_root_layout(&state, &root).unwrap();
+        let mut manifest = read_manifest(&state, &root).unwrap();
+        let story_ids = (0..2)
+            .map(|_| Uuid::new_v4().to_string())
+            .collect::<Vec<_>>();
+        for story_id in &story_ids {
+            let folder_name = unique_story_folder_name(&manifest, "hammer", story_id);
+            write_workspace(
+                &story_db_path(&state, &root, &folder_name),
+                &Workspace {
+                    settings: vec![],
+                    tree: vec![],
+                    library: default_library(),
+                    repaired: false,
+                    recovered: false,
+                },
+            )
+            .unwrap();
+            manifest.stories.push(StoryManifestEntry {
+                story: story(story_id, "hammer"),
+                folder_name,
+                order: None,
+            });
+        }
+        write_manifest(&state, &root, &manifest).unwrap();
+
+        let mut handles = Vec::new();
+        for worker in 0..4 {
+            let state = state.clone();
+            let root = root.clone();
+            handles.push(thread::spawn(move || {
+                for round in 0..10 {
+                    update_library_in_place(&state, &root, None, |library| {
+                        library.categories.push(format!("c-{worker}-{round}"));
+                        Ok(())
+                    })
+                    .unwrap();
+                }
+            }));
+        }
+        for story_id in story_ids {
+            let state = state.clone();
+            let root = root.clone();
+            handles.push(thread::spawn(move || {
+                for _ in 0..10 {
+                    write_story_settings(&state, &root, &story_id, vec![]).unwrap();
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let categories = read_manifest(&state, &root)
+            .unwrap()
+            .shared_library
+            .categories;
+        for worker in 0..4 {
+            for round in 0..10 {
+                assert!(categories.contains(&format!("c-{worker}-{round}")));
+            }
+        }
+    }
+
+    #[test]
+    fn debounced_settings_never_overwrite_a_newer_direct_write() {
+        let root = temp_dir("settings-debounce");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let mut manifest = read_manifest(&state, &root).unwrap();
+        let story_id = Uuid::new_v4().to_string();
+        let folder_name = unique_story_folder_name(&manifest, "debounce", &story_id);
+        manifest.stories.push(StoryManifestEntry {
+            story: story(&story_id, "debounce"),
+            folder_name: folder_name.clone(),
+            order: None,
+        });
+        write_manifest(&state, &root, &manifest).unwrap();
+
+        let setting =
+            |title: &str| serde_json::json!({ "id": Uuid::new_v4().to_string(), "title": title });
+        state.pending_settings.lock().unwrap().insert(
+            story_id.clone(),
+            PendingSettingsWrite {
+                root: root.clone(),
+                settings: vec![setting("old")],
+                due_at: Instant::now(),
+            },
+        );
+        assert_eq!(
+            next_due_settings(&state).as_deref(),
+            Some(story_id.as_str())
+        );
+
+        write_story_settings(&state, &root, &story_id, vec![setting("new")]).unwrap();
+        write_pending_settings(&state, &story_id, true).unwrap();
+        let workspace = read_workspace(&story_db_path(&state, &root, &folder_name)).unwrap();
+        assert_eq!(workspace.settings[0]["title"], "new");
+
+        state.pending_settings.lock().unwrap().insert(
+            story_id.clone(),
+            PendingSettingsWrite {
+                root: root.clone(),
+                settings: vec![setting("later")],
+                due_at: Instant::now() + Duration::from_secs(60),
+            },
+        );
+        write_pending_settings(&state, &story_id, true).unwrap();
+        assert!(state
+            .pending_settings
+            .lock()
+            .unwrap()
+            .contains_key(&story_id));
+        flush_pending_settings(&state).unwrap();
+        assert!(state.pending_settings.lock().unwrap().is_empty());
+        let workspace = read_workspace(&story_db_path(&state, &root, &folder_name)).unwrap();
+        assert_eq!(workspace.settings[0]["title"], "later");
+    }
+
+    #[test]
+    fn undo_delete_restores_assets_and_history() {
+        let root = temp_dir("undo-delete");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let mut manifest = read_manifest(&state, &root).unwrap();
+        let story_id = Uuid::new_v4().to_string();
+        let folder_name = unique_story_folder_name(&manifest, "undo", &story_id);
+        let folder = story_root(&state, &root, &folder_name);
+        fs::create_dir_all(folder.join("assets/images")).unwrap();
+        fs::write(folder.join("assets/images/cover.png"), b"png").unwrap();
+        fs::create_dir_all(story_history_dir(&state, &root, &folder_name)).unwrap();
+        fs::write(story_db_path(&state, &root, &folder_name), b"db").unwrap();
+        manifest.stories.push(StoryManifestEntry {
+            story: story(&story_id, "undo"),
+            folder_name: folder_name.clone(),
+            order: None,
+        });
+        write_manifest(&state, &root, &manifest).unwrap();
+
+        trash_story(&state, &root, &story_id).unwrap();
+        assert!(!folder.exists());
+        assert!(read_manifest(&state, &root).unwrap().stories.is_empty());
+
+        let entry = state.undo_stack.lock().unwrap().pop().unwrap();
+        apply_undo(&state, &root, entry).unwrap();
+        assert_eq!(
+            fs::read(folder.join("assets/images/cover.png")).unwrap(),
+            b"png"
+        );
+        assert!(story_history_dir(&state, &root, &folder_name).is_dir());
+        assert!(find_story_entry(&read_manifest(&state, &root).unwrap(), &story_id).is_some());
+        assert!(!trash_root(&root).join(&story_id).exists());
+
+        trash_story(&state, &root, &story_id).unwrap();
+        clear_undo_stack(&state);
+        assert!(!trash_root(&root).join(&story_id).exists());
+    }
+
+    #[test]
+    fn activation_load_seeds_manifest_cache() {
+        let root = temp_dir("activation-load");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        clear_manifest_cache(&state);
+
+        let (data, warning) = load_project_data_with_warning(&state, &root).unwrap();
+        assert!(data.stories.is_empty());
+        assert!(warning.is_none());
+        let cache = state.manifest_cache.lock().unwrap();
+        let cache = cache.as_ref().unwrap();
+        assert_eq!(cache.root, root);
+        assert_eq!(cache.stamp, manifest_stamp(&root));
+    }
+
+    #[test]
+    fn story_folder_names_avoid_manifest_and_disk_collisions() {
+        let root = temp_dir("folder-collision");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let mut manifest = read_manifest(&state, &root).unwrap();
+        let first = "abcdef12-0000-4000-8000-000000000001";
+        let second = "abcdef12-0000-4000-8000-000000000002";
+        manifest.stories.push(StoryManifestEntry {
+            story: story(first, "Magic"),
+            folder_name: "magic-abcdef12".to_string(),
+            order: None,
+        });
+
+        let created = free_story_folder_name(&state, &root, &manifest, "Magic", second);
+        assert_eq!(created, "magic-abcdef120");
+
+        fs::create_dir_all(story_root(&state, &root, "magic-abcdef120")).unwrap();
+        let created = free_story_folder_name(&state, &root, &manifest, "Magic", second);
+        assert_eq!(created, "magic-abcdef1200");
+
+        fs::create_dir_all(story_root(&state, &root, "magic-abcdef12")).unwrap();
+        assert_eq!(
+            free_story_folder_name(&state, &root, &manifest, "Magic", first),
+            "magic-abcdef12"
+        );
+    }
+
+    #[test]
+    fn backups_in_the_same_second_get_distinct_folders() {
+        let root = temp_dir("backup-names");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let first = create_backup(&state, &root, None).unwrap();
+        let second = create_backup(&state, &root, None).unwrap();
+        assert_ne!(first, second);
+        assert!(first.is_dir() && second.is_dir());
+
+        let parent = temp_dir("backup-names-parent");
+        let taken = new_backup_dir(&parent);
+        fs::create_dir_all(&taken).unwrap();
+        assert_ne!(new_backup_dir(&parent), taken);
+    }
+
+    #[test]
+    fn incremental_backup_state_lives_next_to_external_backups() {
+        let root = temp_dir("incremental-external");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        fs::create_dir_all(stories_root(&state, &root).join("a")).unwrap();
+        fs::write(stories_root(&state, &root).join("a/story.db"), b"db").unwrap();
+
+        let external = temp_dir("incremental-external-target");
+        incremental_backup(&state, &root, &new_backup_dir(&external)).unwrap();
+        assert!(backup_state_path(&external).is_file());
+        assert!(!backup_state_path(&exports_root(&state, &root)).exists());
+
+        let report = incremental_backup(&state, &root, &new_backup_dir(&external)).unwrap();
+        assert!(report.skipped.contains(&"stories/a/story.db".to_string()));
+    }
+
+    #[test]
+    fn categories_fold_case_and_whitespace_on_normalize_and_merge() {
+        let mut categories = vec![
+            "Magic".to_string(),
+            "magic ".to_string(),
+            " ".to_string(),
+            " 角色 ".to_string(),
+            "角色".to_string(),
+        ];
+        assert!(normalize_categories(&mut categories));
+        assert_eq!(categories, vec!["Magic", "角色"]);
+        assert!(!normalize_categories(&mut categories));
+
+        let mut target = SettingLibrary {
+            categories: categories.clone(),
+            ..default_library()
+        };
+        let incoming = SettingLibrary {
+            categories: vec!["MAGIC".to_string(), " 道具 ".to_string()],
+            ..default_library()
+        };
+        assert!(merge_library(&mut target, &incoming));
+        assert_eq!(target.categories, vec!["Magic", "角色", "道具"]);
+        assert!(!merge_library(&mut target, &incoming));
+
+        assert_eq!(
+            rename_library_category(&mut target, "道具", "magic", false).unwrap_err(),
+            "分类已存在: magic"
+        );
+        target.templates.push(
+            serde_json::from_value(serde_json::json!({
+                "id": "spell",
+                "name": "Spell",
+                "preset": { "type": "item", "category": " MAGIC " }
+            }))
+            .unwrap(),
+        );
+        rename_library_category(&mut target, "magic", "Magick", false).unwrap();
+        assert_eq!(target.categories, vec!["Magick", "角色", "道具"]);
+        assert_eq!(
+            target.templates[0].preset.category.as_deref(),
+            Some("Magick")
+        );
+
+        let mut nodes = vec![
+            serde_json::json!({ "id": "a", "category": "Magic" }),
+            serde_json::json!({ "id": "b", "category": " magic" }),
+            serde_json::json!({ "id": "c", "category": "角色" }),
+        ];
+        assert_eq!(rename_node_categories(&mut nodes, "magic", "Magick"), 2);
+        assert_eq!(nodes[0]["category"], "Magick");
+        assert_eq!(nodes[1]["category"], "Magick");
+        assert_eq!(nodes[2]["category"], "角色");
+    }
+
+    #[test]
+    fn duplicate_manifest_folders_are_split_without_losing_data() {
+        let root = temp_dir("folder-repair");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let mut manifest = read_manifest(&state, &root).unwrap();
+        let first = Uuid::new_v4().to_string();
+        let second = Uuid::new_v4().to_string();
+        for story_id in [&first, &second] {
+            manifest.stories.push(StoryManifestEntry {
+                story: story(story_id, "Shared"),
+                folder_name: "shared".to_string(),
+                order: None,
+            });
+        }
+        let workspace = Workspace {
+            settings: vec![serde_json::json!({ "id": "n1", "title": "kept" })],
+            tree: vec![],
+            library: default_library(),
+            repaired: false,
+            recovered: false,
+        };
+        write_workspace(&story_db_path(&state, &root, "shared"), &workspace).unwrap();
+        fs::create_dir_all(story_root(
+            &state,
+            &root,
+            &make_story_folder_name("Shared", &second),
+        ))
+        .unwrap();
+
+        let repairs = repair_folder_name_collisions(&state, &root, &mut manifest).unwrap();
+        assert_eq!(repairs.len(), 1);
+        assert_eq!(repairs[0].story_id, second);
+        assert_eq!(manifest.stories[0].folder_name, "shared");
+        assert_ne!(
+            repairs[0].new_folder_name,
+            make_story_folder_name("Shared", &second)
+        );
+        assert!(duplicate_folder_names(&manifest).is_empty());
+        for entry in &manifest.stories {
+            let db_path = story_db_path(&state, &root, &entry.folder_name);
+            assert_eq!(
+                read_workspace(&db_path).unwrap().settings[0]["title"],
+                "kept"
+            );
+        }
+    }
+
+    fn story_payload(story: Story, folder_name: &str) -> ExportedStoryData {
+        ExportedStoryData {
+            app: "takecopter".to_string(),
+            schema_version: CURRENT_SCHEMA_VERSION,
+            exported_at: now_rfc3339(),
+            story,
+            workspace: Workspace {
+                settings: vec![],
+                tree: vec![],
+                library: default_library(),
+                repaired: false,
+                recovered: false,
+            },
+            workspace_updated_at: None,
+            folder_name: Some(folder_name.to_string()),
+            assets: HashMap::new(),
+            missing_assets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn imported_story_with_colliding_folder_keeps_both_stories() {
+        let root = temp_dir("import-collision");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let existing = "abcdef12-0000-4000-8000-000000000001";
+        let crafted = "abcdef12-0000-4000-8000-000000000002";
+        let folder_name = make_story_folder_name("Magic", existing);
+
+        let mut first = story_payload(story(existing, "Magic"), &folder_name);
+        first.workspace.settings = vec![serde_json::json!({ "id": "n1", "title": "original" })];
+        import_story_payload(&state, &root, first).unwrap();
+        let mut second = story_payload(story(crafted, "Magic"), &folder_name);
+        second.workspace.settings = vec![serde_json::json!({ "id": "n2", "title": "imported" })];
+        import_story_payload(&state, &root, second).unwrap();
+
+        let manifest = read_manifest(&state, &root).unwrap();
+        assert_eq!(manifest.stories.len(), 2);
+        assert!(duplicate_folder_names(&manifest).is_empty());
+        for (story_id, title) in [(existing, "original"), (crafted, "imported")] {
+            let entry = find_story_entry(&manifest, story_id).unwrap();
+            let db_path = story_db_path(&state, &root, &entry.folder_name);
+            assert_eq!(
+                read_workspace(&db_path).unwrap().settings[0]["title"],
+                title
+            );
+        }
+    }
+
+    #[test]
+    fn read_only_reads_leave_story_db_untouched() {
+        let dir = temp_dir("read-only-db");
+        let db_path = dir.join("story.db");
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE workspace (id INTEGER PRIMARY KEY, settings_json TEXT NOT NULL, tree_json TEXT NOT NULL);
+                 INSERT INTO workspace (id, settings_json, tree_json) VALUES (1, '[{\"id\":\"n1\",\"title\":\"dragon\"}]', '[]');",
+            )
+            .unwrap();
+        let before = fs::read(&db_path).unwrap();
+
+        let state = ProjectState::default();
+        state.read_only.store(true, Ordering::Relaxed);
+        let workspace = read_story_workspace(&state, &db_path).unwrap();
+        assert_eq!(workspace.settings[0]["title"], "dragon");
+        assert_eq!(workspace.library.categories, default_library().categories);
+        assert!(read_workspace_touched_at(&db_path, true).is_none());
+        assert_eq!(
+            read_library_from_db(&db_path, true).unwrap().categories,
+            default_library().categories
+        );
+        let hits = search_story_index(&db_path, "dragon", 10, true).unwrap();
+        assert_eq!(hits[0].0, "n1");
+        assert_eq!(fs::read(&db_path).unwrap(), before);
+        assert!(!dir.join("assets").exists());
+
+        let corrupt = dir.join("corrupt.db");
+        fs::write(&corrupt, b"not a database at all, just some bytes").unwrap();
+        assert!(read_story_workspace(&state, &corrupt).is_err());
+        assert!(corrupt.is_file());
+    }
+
+    #[test]
+    fn imported_image_reference_resolves_after_rewrite_sanitize_and_materialize() {
+        let root = temp_dir("import-assets");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let story_id = Uuid::new_v4().to_string();
+        let mut payload = story_payload(story(&story_id, "Assets"), "old-folder");
+        payload.workspace.settings = vec![
+            serde_json::json!({
+                "id": "n1",
+                "imageUrl": "C:\\Users\\me\\stories\\old-folder\\assets\\images\\cover.png",
+            }),
+            serde_json::json!({ "id": "n2", "imageUrl": "../../../etc/passwd" }),
+        ];
+        payload.assets.insert(
+            "assets/images/cover.png".to_string(),
+            BASE64.encode(b"cover-bytes"),
+        );
+
+        import_story_payload(&state, &root, payload).unwrap();
+        let manifest = read_manifest(&state, &root).unwrap();
+        let entry = find_story_entry(&manifest, &story_id).unwrap();
+        let story_dir = story_root(&state, &root, &entry.folder_name);
+        let workspace = read_workspace(&story_db_path(&state, &root, &entry.folder_name)).unwrap();
+
+        let reference = workspace.settings[0]["imageUrl"].as_str().unwrap();
+        assert_eq!(reference, "assets/images/cover.png");
+        let path = story_asset_path(&story_dir, reference).unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"cover-bytes");
+        assert_eq!(workspace.settings[1]["imageUrl"], "");
+    }
+
+    #[test]
+    fn malformed_import_timestamps_are_reported() {
+        let mut invalid = Vec::new();
+        assert_eq!(
+            normalize_import_timestamp("2024-03-01T08:00:00+08:00", &mut invalid),
+            "2024-03-01T00:00:00.000Z"
+        );
+        assert!(invalid.is_empty());
+        let fallback = normalize_import_timestamp("yesterday-ish", &mut invalid);
+        assert!(parse_timestamp(&fallback).is_some());
+        normalize_import_timestamp("", &mut invalid);
+        assert_eq!(invalid, vec!["yesterday-ish".to_string(), String::new()]);
+
+        let root = temp_dir("import-timestamps");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let story_id = Uuid::new_v4().to_string();
+        let mut imported = story(&story_id, "Times");
+        imported.updated_at = "31/02/2024".to_string();
+        let mut payload = story_payload(imported, "times");
+        payload.workspace_updated_at = Some("not a time".to_string());
+
+        let result = import_story_payload(&state, &root, payload).unwrap();
+        assert_eq!(
+            result.warning.unwrap().invalid_timestamps,
+            vec!["31/02/2024".to_string(), "not a time".to_string()]
+        );
+        let manifest = read_manifest(&state, &root).unwrap();
+        let entry = find_story_entry(&manifest, &story_id).unwrap();
+        assert!(parse_timestamp(&entry.story.updated_at).is_some());
+    }
+
+    #[test]
+    fn touched_at_trigger_stamps_milliseconds() {
+        let db_path = temp_dir("touched-at-trigger").join("story.db");
+        let conn = open_story_db(&db_path).unwrap();
+        conn.execute(
+            "INSERT INTO workspace (id, settings_json, tree_json) VALUES (1, '[]', '[]')",
+            [],
+        )
+        .unwrap();
+        conn.execute("UPDATE workspace SET tree_json = '[1]' WHERE id = 1", [])
+            .unwrap();
+
+        let triggers: Vec<String> = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'trigger'")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(triggers, vec!["workspace_touched_at".to_string()]);
+        let touched = read_workspace_touched_at(&db_path, false).unwrap();
+        assert_eq!(touched.len(), "2024-01-01T00:00:00.000Z".len(), "{touched}");
+    }
+
+    #[test]
+    fn import_flushes_pending_settings_before_overwriting() {
+        let root = temp_dir("import-pending");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let mut manifest = read_manifest(&state, &root).unwrap();
+        let story_id = Uuid::new_v4().to_string();
+        let folder_name = unique_story_folder_name(&manifest, "pending", &story_id);
+        write_workspace(
+            &story_db_path(&state, &root, &folder_name),
+            &Workspace {
+                settings: vec![],
+                tree: vec![],
+                library: default_library(),
+                repaired: false,
+                recovered: false,
+            },
+        )
+        .unwrap();
+        manifest.stories.push(StoryManifestEntry {
+            story: story(&story_id, "pending"),
+            folder_name: folder_name.clone(),
+            order: None,
+        });
+        write_manifest(&state, &root, &manifest).unwrap();
+
+        state.pending_settings.lock().unwrap().insert(
+            story_id.clone(),
+            PendingSettingsWrite {
+                root: root.clone(),
+                settings: vec![serde_json::json!({ "id": "local", "title": "local" })],
+                due_at: Instant::now() + Duration::from_secs(60),
+            },
+        );
+
+        let imported = Workspace {
+            settings: vec![serde_json::json!({ "id": "imported", "title": "imported" })],
+            tree: vec![],
+            library: default_library(),
+            repaired: false,
+            recovered: false,
+        };
+        let payload = ExportedProjectData {
+            app: "takecopter".to_string(),
+            schema_version: CURRENT_SCHEMA_VERSION,
+            exported_at: now_rfc3339(),
+            data: ProjectData {
+                stories: vec![story(&story_id, "pending")],
+                workspaces: HashMap::from([(story_id.clone(), imported)]),
+                shared_library: default_library(),
+            },
+        };
+        import_project_payload(&state, &root, payload, ImportWarning::default()).unwrap();
+
+        assert!(state.pending_settings.lock().unwrap().is_empty());
+        flush_pending_settings(&state).unwrap();
+        let manifest = read_manifest(&state, &root).unwrap();
+        let entry = find_story_entry(&manifest, &story_id).unwrap();
+        let workspace = read_workspace(&story_db_path(&state, &root, &entry.folder_name)).unwrap();
+        assert_eq!(workspace.settings[0]["title"], "imported");
+    }
+
+    #[test]
+    fn recategorize_uses_library_spelling_for_case_variants() {
+        let mut workspace = Workspace {
+            settings: vec![
+                serde_json::json!({ "id": "a", "category": "角色" }),
+                serde_json::json!({ "id": "b", "category": "Magic" }),
+                serde_json::json!({ "id": "c", "category": "角色" }),
+            ],
+            tree: vec![],
+            library: SettingLibrary {
+                categories: vec!["Magic".to_string(), "角色".to_string()],
+                ..default_library()
+            },
+            repaired: false,
+            recovered: false,
+        };
+        let ids = vec!["a".to_string(), "b".to_string()];
+
+        assert_eq!(
+            recategorize_nodes(&mut workspace, &ids, "magic", false).unwrap(),
+            1
+        );
+        assert_eq!(workspace.settings[0]["category"], "Magic");
+        assert_eq!(workspace.settings[2]["category"], "角色");
+        assert_eq!(
+            recategorize_nodes(&mut workspace, &ids, "MAGIC", true).unwrap(),
+            0
+        );
+        assert_eq!(workspace.library.categories, vec!["Magic", "角色"]);
+
+        assert_eq!(
+            recategorize_nodes(&mut workspace, &ids, "道具", false).unwrap_err(),
+            "分类不存在: 道具"
+        );
+        assert_eq!(
+            recategorize_nodes(&mut workspace, &ids, "道具", true).unwrap(),
+            2
+        );
+        assert_eq!(workspace.library.categories, vec!["Magic", "角色", "道具"]);
+    }
+
+    #[test]
+    fn merged_projects_drop_unsafe_asset_references() {
+        let root = temp_dir("merge-sanitize");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let story_id = Uuid::new_v4().to_string();
+        let template = |image_url: &str| -> SettingTemplate {
+            serde_json::from_value(serde_json::json!({
+                "id": Uuid::new_v4().to_string(),
+                "name": "Portrait",
+                "preset": { "type": "character", "imageUrl": image_url }
+            }))
+            .unwrap()
+        };
+        let workspace = Workspace {
+            settings: vec![serde_json::json!({ "id": "a", "imageUrl": "../../secret.png" })],
+            tree: vec![],
+            library: SettingLibrary {
+                templates: vec![template("/etc/passwd")],
+                ..default_library()
+            },
+            repaired: false,
+            recovered: false,
+        };
+        let payload = ExportedProjectData {
+            app: "takecopter".to_string(),
+            schema_version: CURRENT_SCHEMA_VERSION,
+            exported_at: now_rfc3339(),
+            data: ProjectData {
+                stories: vec![story(&story_id, "merged")],
+                workspaces: HashMap::from([(story_id.clone(), workspace)]),
+                shared_library: SettingLibrary {
+                    templates: vec![template("..\\..\\boot.ini")],
+                    ..default_library()
+                },
+            },
+        };
+
+        merge_project_payload(&state, &root, payload, MergeStrategy::default(), Vec::new())
+            .unwrap();
+        let manifest = read_manifest(&state, &root).unwrap();
+        assert_eq!(manifest.shared_library.templates.len(), 1);
+        assert!(manifest.shared_library.templates[0]
+            .preset
+            .image_url
+            .is_none());
+        let entry = find_story_entry(&manifest, &story_id).unwrap();
+        let merged = read_workspace(&story_db_path(&state, &root, &entry.folder_name)).unwrap();
+        assert_eq!(merged.settings[0]["imageUrl"], "");
+        assert!(merged.library.templates[0].preset.image_url.is_none());
+    }
+
+    #[test]
+    fn project_import_and_merge_skip_folders_already_on_disk() {
+        let root = temp_dir("project-import-collision");
+        let state = ProjectState::default();
+        ensure_root_layout(&state, &root).unwrap();
+        let manifest = read_manifest(&state, &root).unwrap();
+        let imported_id = Uuid::new_v4().to_string();
+        let merged_id = Uuid::new_v4().to_string();
+        let mut leftovers = Vec::new();
+        for (story_id, title) in [(&imported_id, "Imported"), (&merged_id, "Merged")] {
+            let folder_name = unique_story_folder_name(&manifest, title, story_id);
+            let folder = story_root(&state, &root, &folder_name);
+            fs::create_dir_all(&folder).unwrap();
+            fs::write(folder.join("leftover.txt"), "old").unwrap();
+            leftovers.push(folder_name);
+        }
+        let project = |story_id: &str, title: &str| ExportedProjectData {
+            app: "takecopter".to_string(),
+            schema_version: CURRENT_SCHEMA_VERSION,
+            exported_at: now_rfc3339(),
+            data: ProjectData {
+                stories: vec![story(story_id, title)],
+                workspaces: HashMap::new(),
+                shared_library: default_library(),
+            },
+        };
+
+        import_project_payload(
+            &state,
+            &root,
+            project(&imported_id, "Imported"),
+            ImportWarning::default(),
+        )
+        .unwrap();
+        let first_folder = read_manifest(&state, &root).unwrap().stories[0]
+            .folder_name
+            .clone();
+        assert!(!leftovers.contains(&first_folder));
+        import_project_payload(
+            &state,
+            &root,
+            project(&imported_id, "Renamed"),
+            ImportWarning::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            read_manifest(&state, &root).unwrap().stories[0].folder_name,
+            first_folder
+        );
+
+        merge_project_payload(
+            &state,
+            &root,
+            project(&merged_id, "Merged"),
+            MergeStrategy::default(),
+            Vec::new(),
+        )
+        .unwrap();
+        let manifest = read_manifest(&state, &root).unwrap();
+        let merged = find_story_entry(&manifest, &merged_id).unwrap();
+        assert!(!leftovers.contains(&merged.folder_name));
+        for folder_name in &leftovers {
+            let folder = story_root(&state, &root, folder_name);
+            assert_eq!(
+                fs::read_to_string(folder.join("leftover.txt")).unwrap(),
+                "old"
+            );
+            assert!(!folder.join("story.db").exists());
+        }
+    }
 }
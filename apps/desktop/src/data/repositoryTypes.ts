@@ -4,6 +4,10 @@ export interface BootstrapState {
   needsSetup: boolean;
   defaultRootPath: string;
   activeRootPath: string | null;
+  schemaVersion?: number | null;
+  appVersion?: string;
+  needsUpgrade?: boolean;
+  readOnly?: boolean;
 }
 
 export interface ExportedProjectData {
@@ -28,6 +32,7 @@ export interface ExportedStoryData {
 export interface CreateStoryInput {
   title: string;
   description: string;
+  coverColor?: string;
 }
 
 export interface ProjectDataRepository {
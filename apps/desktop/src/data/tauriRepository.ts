@@ -11,6 +11,13 @@ import type {
 interface EnsureProjectResponse {
   projectPath: string;
   data: SerializedProjectData;
+  lastStoryId?: string | null;
+  recoveredStoryIds?: string[];
+}
+
+interface CreateStoryResponse {
+  story: SerializedStory;
+  workspace: SerializedWorkspace;
 }
 
 type SerializedStory = Omit<Story, 'updatedAt'> & { updatedAt: string };
@@ -18,6 +25,8 @@ type SerializedWorkspace = {
   settings?: SettingCard[];
   tree?: TreeNode[];
   library?: unknown;
+  repaired?: boolean;
+  recovered?: boolean;
 };
 
 interface SerializedProjectData {
@@ -219,8 +228,8 @@ class TauriRepository implements ProjectDataRepository {
   }
 
   async createStory(input: CreateStoryInput): Promise<Story> {
-    const story = await invoke<SerializedStory>('create_story', { input });
-    return hydrateStory(story);
+    const response = await invoke<CreateStoryResponse>('create_story', { input });
+    return hydrateStory(response.story);
   }
 
   async renameStory(storyId: string, title: string): Promise<Story> {
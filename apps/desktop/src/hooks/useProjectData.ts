@@ -229,8 +229,8 @@ export function useProjectData(): UseProjectDataResult {
 
   const exportProjectFile = useCallback(async () => {
     if (isTauriRuntime()) {
-      const folder = await repository.exportProjectToLocal();
-      window.alert(`已导出项目并打开目录：${folder}`);
+      const filePath = await repository.exportProjectToLocal();
+      window.alert(`已导出项目：${filePath}`);
       return;
     }
 
@@ -247,8 +247,8 @@ export function useProjectData(): UseProjectDataResult {
   const exportStoryFile = useCallback(
     async (storyId: string) => {
       if (isTauriRuntime()) {
-        const folder = await repository.exportStoryToLocal(storyId);
-        window.alert(`已导出故事并打开目录：${folder}`);
+        const filePath = await repository.exportStoryToLocal(storyId);
+        window.alert(`已导出故事：${filePath}`);
         return;
       }
 